@@ -0,0 +1,376 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+// prices of a unit on the steps that traded, oldest first
+pub fn price_series(history: &History) -> Vec<f64> {
+    history
+        .recent()
+        .flat_map(History::unit_price)
+        .map(|price| price.as_int as f64)
+        .collect()
+}
+
+fn average(prices: &[f64]) -> f64 {
+    prices.iter().sum::<f64>() / prices.len() as f64
+}
+
+/// What a chartist reads from the prices. Thresholds are in basis points of the
+/// price they are measured against.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Rule {
+    // buys after the price rose over the last `lookback` steps, sells after it fell
+    Momentum { lookback: usize, threshold_bps: i64 },
+    // sells when the price is above its moving average, buys below it
+    MeanReversion { window: usize, threshold_bps: i64 },
+    // buys while the short moving average is above the long one, sells while below
+    Crossover { short: usize, long: usize },
+}
+
+impl Rule {
+    // the side to trade on, `None` without a signal or enough prices
+    pub fn signal(&self, prices: &[f64]) -> Option<OrderSide> {
+        let last = *prices.last()?;
+        let beyond = |reference: f64, threshold_bps: i64| {
+            let threshold = reference * threshold_bps as f64 / 10_000.0;
+            if last > reference + threshold {
+                Some(OrderSide::Bid)
+            } else if last < reference - threshold {
+                Some(OrderSide::Ask)
+            } else {
+                None
+            }
+        };
+        let opposite = |side: OrderSide| match side {
+            OrderSide::Bid => OrderSide::Ask,
+            OrderSide::Ask => OrderSide::Bid,
+        };
+
+        match *self {
+            Rule::Momentum {
+                lookback,
+                threshold_bps,
+            } => {
+                let start = prices.len().checked_sub(lookback + 1)?;
+                beyond(prices[start], threshold_bps)
+            }
+            Rule::MeanReversion {
+                window,
+                threshold_bps,
+            } => {
+                let start = prices.len().checked_sub(window)?;
+                beyond(average(&prices[start..]), threshold_bps).map(opposite)
+            }
+            Rule::Crossover { short, long } => {
+                let start = prices.len().checked_sub(long)?;
+                let long = average(&prices[start..]);
+                let short = average(&prices[prices.len() - short.min(prices.len())..]);
+
+                if short > long {
+                    Some(OrderSide::Bid)
+                } else if short < long {
+                    Some(OrderSide::Ask)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aggressiveness {
+    Market,
+    // limit orders `offset` beyond the reference price, into the other side of the book
+    Limit { offset: Amount },
+}
+
+impl Aggressiveness {
+    // `size` to `side` around `reference`, none when a limit price wouldn't be positive
+    pub fn order(&self, side: OrderSide, size: Quantity, reference: Amount) -> Option<OrderData> {
+        let price = match *self {
+            Aggressiveness::Market => None,
+            Aggressiveness::Limit { offset } => {
+                let price = match side {
+                    OrderSide::Bid => reference + offset,
+                    OrderSide::Ask => reference - offset,
+                };
+                if price.as_int <= 0 {
+                    return None;
+                }
+                Some(price)
+            }
+        };
+        Some(OrderData { side, price, size })
+    }
+}
+
+/// Commodity a trader holds beyond what it had at its first step, kept within `max`
+/// units either way.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PositionLimit {
+    pub max: Quantity,
+
+    // commodity held at the first step
+    start: Option<Quantity>,
+}
+
+impl PositionLimit {
+    pub fn new(max: Quantity) -> Self {
+        Self { max, start: None }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(Quantity::MAX)
+    }
+
+    // called every step, the first call fixes the start
+    pub fn observe(&mut self, account: &Account) {
+        self.start.get_or_insert(account.commodity);
+    }
+
+    // units held beyond the start, negative once below it
+    pub fn position(&self, account: &Account) -> Quantity {
+        account.commodity - self.start.unwrap_or(account.commodity)
+    }
+
+    // units that can still go to `side` within the limit
+    pub fn room(&self, account: &Account, side: OrderSide) -> Quantity {
+        let position = self.position(account);
+        match side {
+            OrderSide::Bid => self.max.saturating_sub(position),
+            OrderSide::Ask => self.max.saturating_add(position),
+        }
+    }
+}
+
+/// Trades `size` a step on the signal of its rule, as long as its position stays
+/// within `limit`. The rules need more than the last step, so the history has to
+/// carry a window.
+pub struct Chartist<T> {
+    pub rule: Rule,
+    pub size: Quantity,
+    pub limit: PositionLimit,
+    pub aggressiveness: Aggressiveness,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> Chartist<T> {
+    pub fn new(rule: Rule, size: Quantity, max_position: Quantity) -> Self {
+        Self {
+            rule,
+            size,
+            limit: PositionLimit::new(max_position),
+            aggressiveness: Aggressiveness::Market,
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn momentum(lookback: usize, size: Quantity, max_position: Quantity) -> Self {
+        let rule = Rule::Momentum {
+            lookback,
+            threshold_bps: 0,
+        };
+        Self::new(rule, size, max_position)
+    }
+
+    pub fn mean_reversion(window: usize, size: Quantity, max_position: Quantity) -> Self {
+        let rule = Rule::MeanReversion {
+            window,
+            threshold_bps: 0,
+        };
+        Self::new(rule, size, max_position)
+    }
+
+    pub fn crossover(short: usize, long: usize, size: Quantity, max_position: Quantity) -> Self {
+        assert!(short < long, "the short average has to be shorter");
+        Self::new(Rule::Crossover { short, long }, size, max_position)
+    }
+
+    pub fn with_aggressiveness(mut self, aggressiveness: Aggressiveness) -> Self {
+        self.aggressiveness = aggressiveness;
+        self
+    }
+
+    pub fn position(&self, account: &Account) -> Quantity {
+        self.limit.position(account)
+    }
+}
+
+impl<T> Agent for Chartist<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, _rng: SimRng) {}
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.limit.observe(account);
+
+        let prices = price_series(history);
+        let Some(side) = self.rule.signal(&prices) else {
+            return vec![];
+        };
+
+        let size = self.size.min(self.limit.room(account, side));
+        if !size.is_positive() {
+            return vec![];
+        }
+
+        let last = Amount {
+            as_int: prices[prices.len() - 1] as i64,
+        };
+        self.aggressiveness
+            .order(side, size, last)
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_market::{COMMODITY, TestAgent, WINDOW, market, run, traded_at, traded_lots_at},
+        zero_intelligence::ZeroIntelligence,
+    };
+    use market::market::MarketInfo;
+
+    type Trader = Chartist<()>;
+
+    fn orders(trader: &mut Trader, units: i64, history: &History) -> Vec<OrderData> {
+        let info = MarketInfo {
+            name: "chartist".to_owned(),
+            commodity: (),
+        };
+        let account = Account {
+            commodity: Quantity::new(units),
+            ..Default::default()
+        };
+        trader.produce_orders(&account, &info, history)
+    }
+
+    #[test]
+    fn rules_read_the_price_history() {
+        let rising = [100.0, 101.0, 103.0, 106.0, 110.0];
+        let falling = [110.0, 106.0, 103.0, 101.0, 100.0];
+
+        let momentum = Rule::Momentum {
+            lookback: 3,
+            threshold_bps: 0,
+        };
+        assert_eq!(momentum.signal(&rising), Some(OrderSide::Bid));
+        assert_eq!(momentum.signal(&falling), Some(OrderSide::Ask));
+        assert_eq!(momentum.signal(&rising[..3]), None);
+
+        let strict = Rule::Momentum {
+            lookback: 1,
+            threshold_bps: 500,
+        };
+        assert_eq!(strict.signal(&rising), None);
+
+        let reversion = Rule::MeanReversion {
+            window: 5,
+            threshold_bps: 100,
+        };
+        assert_eq!(reversion.signal(&rising), Some(OrderSide::Ask));
+        assert_eq!(reversion.signal(&falling), Some(OrderSide::Bid));
+        assert_eq!(reversion.signal(&[100.0; 5]), None);
+
+        let crossover = Rule::Crossover { short: 2, long: 5 };
+        assert_eq!(crossover.signal(&rising), Some(OrderSide::Bid));
+        assert_eq!(crossover.signal(&falling), Some(OrderSide::Ask));
+        assert_eq!(crossover.signal(&rising[..4]), None);
+    }
+
+    #[test]
+    fn orders_respect_position_and_aggressiveness() {
+        let rising = traded_at(&[100, 102, 104, 106], WINDOW);
+
+        let mut trader = Trader::momentum(2, Quantity::new(3), Quantity::new(4));
+        let first = orders(&mut trader, 10, &rising);
+        assert_eq!(first[0].side, OrderSide::Bid);
+        assert_eq!(first[0].price, None);
+        assert_eq!(first[0].size, Quantity::new(3));
+
+        // one unit of room left, then none
+        assert_eq!(orders(&mut trader, 13, &rising)[0].size, Quantity::new(1));
+        assert!(orders(&mut trader, 14, &rising).is_empty());
+
+        let mut limit = Trader::mean_reversion(4, Quantity::new(1), Quantity::new(4))
+            .with_aggressiveness(Aggressiveness::Limit {
+                offset: Amount { as_int: 2 },
+            });
+        let ask = orders(&mut limit, 10, &rising);
+        assert_eq!(ask[0].side, OrderSide::Ask);
+        assert_eq!(ask[0].price, Some(Amount { as_int: 104 }));
+    }
+
+    #[test]
+    fn larger_fills_are_read_per_unit() {
+        let lots = traded_lots_at(&[100, 102, 104, 106], Quantity::new(3), WINDOW);
+        assert_eq!(price_series(&lots), [100.0, 102.0, 104.0, 106.0]);
+
+        let mut limit = Trader::momentum(2, Quantity::new(1), Quantity::new(4))
+            .with_aggressiveness(Aggressiveness::Limit {
+                offset: Amount { as_int: 2 },
+            });
+        let bid = orders(&mut limit, 10, &lots);
+        assert_eq!(bid[0].side, OrderSide::Bid);
+        assert_eq!(bid[0].price, Some(Amount { as_int: 108 }));
+    }
+
+    #[test]
+    fn positions_stay_limited_among_noise_traders() {
+        let noise = |side| {
+            let values = vec![Amount { as_int: 0 }; 500];
+            let trader = ZeroIntelligence::unconstrained(
+                side,
+                values,
+                Amount { as_int: 80 },
+                Amount { as_int: 120 },
+            );
+            Box::new(trader) as TestAgent
+        };
+        let chartists: Vec<TestAgent> = vec![
+            Box::new(Trader::momentum(3, Quantity::new(1), Quantity::new(5))),
+            Box::new(Trader::mean_reversion(
+                5,
+                Quantity::new(1),
+                Quantity::new(5),
+            )),
+            Box::new(
+                Trader::crossover(2, 6, Quantity::new(1), Quantity::new(5)).with_aggressiveness(
+                    Aggressiveness::Limit {
+                        offset: Amount { as_int: 5 },
+                    },
+                ),
+            ),
+        ];
+        let agents = chartists
+            .into_iter()
+            .chain([noise(OrderSide::Bid), noise(OrderSide::Ask)])
+            .chain([noise(OrderSide::Bid), noise(OrderSide::Ask)])
+            .collect();
+
+        let mut market = market(2);
+        let (ids, _) = run(&mut market, agents, 150);
+
+        let positions: Vec<i64> = ids[..3]
+            .iter()
+            .map(|id| market.account(*id).unwrap().commodity.as_int - COMMODITY)
+            .collect();
+        assert!(positions.iter().all(|p| p.abs() <= 5), "{positions:?}");
+        assert!(positions.iter().any(|&p| p != 0), "{positions:?}");
+    }
+}
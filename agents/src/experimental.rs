@@ -0,0 +1,359 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::math::exp;
+
+/// Roth–Erev reinforcement in the modified form of Erev and Roth (1998). Every
+/// propensity decays by `recency`, the chosen price is reinforced with its payoff
+/// and the others share `experimentation` of it. Prices are chosen in proportion
+/// to their propensities.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RothErev {
+    pub recency: f64,
+    pub experimentation: f64,
+    pub propensities: Vec<f64>,
+}
+
+impl RothErev {
+    pub fn new(actions: usize, initial: f64) -> Self {
+        Self {
+            recency: 0.1,
+            experimentation: 0.2,
+            propensities: vec![initial; actions],
+        }
+    }
+
+    pub fn learn(&mut self, chosen: usize, payoff: f64) {
+        let others = (self.propensities.len() - 1).max(1) as f64;
+        for (action, propensity) in self.propensities.iter_mut().enumerate() {
+            let reinforcement = if action == chosen {
+                payoff * (1.0 - self.experimentation)
+            } else {
+                payoff * self.experimentation / others
+            };
+            // losses wear a propensity down to nothing, never below
+            *propensity = ((1.0 - self.recency) * *propensity + reinforcement).max(0.0);
+        }
+    }
+
+    pub fn probabilities(&self) -> Vec<f64> {
+        normalise(self.propensities.clone())
+    }
+}
+
+/// Experience-weighted attraction of Camerer and Ho (1999). Attractions decay by
+/// `phi` and experience by `rho`. Foregone payoffs count `delta` of what the chosen
+/// price earned. Prices are chosen by a logit of the attractions with sensitivity
+/// `lambda`. With `delta` 0 it is a cumulative reinforcement model, with `delta` 1
+/// and `rho` equal to `phi` it is weighted fictitious play.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ewa {
+    pub delta: f64,
+    pub phi: f64,
+    pub rho: f64,
+    pub lambda: f64,
+    pub attractions: Vec<f64>,
+    pub experience: f64,
+}
+
+impl Ewa {
+    pub fn new(actions: usize) -> Self {
+        Self {
+            delta: 0.5,
+            phi: 0.9,
+            rho: 0.9,
+            lambda: 0.1,
+            attractions: vec![0.0; actions],
+            experience: 1.0,
+        }
+    }
+
+    // `payoffs` has what every price earned or would have earned
+    pub fn learn(&mut self, chosen: usize, payoffs: &[f64]) {
+        let experience = self.rho * self.experience + 1.0;
+        for (action, attraction) in self.attractions.iter_mut().enumerate() {
+            let weight = if action == chosen { 1.0 } else { self.delta };
+            *attraction =
+                (self.phi * self.experience * *attraction + weight * payoffs[action]) / experience;
+        }
+        self.experience = experience;
+    }
+
+    pub fn probabilities(&self) -> Vec<f64> {
+        let max = self.attractions.iter().copied().fold(f64::MIN, f64::max);
+        let weights = self.attractions.iter();
+        normalise(weights.map(|a| exp(self.lambda * (a - max))).collect())
+    }
+}
+
+fn normalise(mut weights: Vec<f64>) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    let len = weights.len() as f64;
+    for weight in &mut weights {
+        *weight = if total > 0.0 {
+            *weight / total
+        } else {
+            1.0 / len
+        };
+    }
+    weights
+}
+
+// an index drawn with the given probabilities
+fn draw(probabilities: &[f64], rng: &mut SimRng) -> usize {
+    let mut point = rng.next_f64();
+    for (action, &probability) in probabilities.iter().enumerate() {
+        if point < probability {
+            return action;
+        }
+        point -= probability;
+    }
+    probabilities.len() - 1
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Model {
+    RothErev(RothErev),
+    Ewa(Ewa),
+}
+
+/// Trader from the experimental economics literature. Every step it quotes `size`
+/// at one price of its grid, drawn by its learning model, and learns from what that
+/// earned against its `valuation`: a redemption value when buying, a cost when
+/// selling. EWA also learns what the other prices would have earned, taking a
+/// price to have traded when it reached the best opposite price of the last step.
+pub struct LabTrader<T> {
+    pub side: OrderSide,
+    pub prices: Vec<Amount>,
+    pub size: Quantity,
+    pub valuation: Amount,
+    pub model: Model,
+
+    rng: SimRng,
+    last: Option<(usize, Account)>,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> LabTrader<T> {
+    pub fn new(side: OrderSide, prices: Vec<Amount>, valuation: Amount, model: Model) -> Self {
+        assert!(
+            !prices.is_empty(),
+            "a lab trader needs prices to choose from"
+        );
+        Self {
+            side,
+            prices,
+            size: Quantity::new(1),
+            valuation,
+            model,
+            rng: SimRng::seed_from_u64(0),
+            last: None,
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn roth_erev(side: OrderSide, prices: Vec<Amount>, valuation: Amount) -> Self {
+        let model = Model::RothErev(RothErev::new(prices.len(), 1.0));
+        Self::new(side, prices, valuation, model)
+    }
+
+    pub fn ewa(side: OrderSide, prices: Vec<Amount>, valuation: Amount) -> Self {
+        let model = Model::Ewa(Ewa::new(prices.len()));
+        Self::new(side, prices, valuation, model)
+    }
+
+    pub fn with_size(mut self, size: Quantity) -> Self {
+        self.size = size;
+        self
+    }
+
+    // Roth–Erev propensities or EWA attractions, one for every price
+    pub fn propensities(&self) -> &[f64] {
+        match &self.model {
+            Model::RothErev(model) => &model.propensities,
+            Model::Ewa(model) => &model.attractions,
+        }
+    }
+
+    pub fn probabilities(&self) -> Vec<f64> {
+        match &self.model {
+            Model::RothErev(model) => model.probabilities(),
+            Model::Ewa(model) => model.probabilities(),
+        }
+    }
+
+    // surplus a fill at `price` makes against the valuation
+    fn surplus(&self, price: Amount) -> f64 {
+        let surplus = match self.side {
+            OrderSide::Bid => self.valuation - price,
+            OrderSide::Ask => price - self.valuation,
+        };
+        (surplus * self.size.as_int).as_int as f64
+    }
+
+    // what every price would have earned in the last step
+    pub fn foregone(&self, history: &History) -> Vec<f64> {
+        let mut opposite: Vec<Amount> = history
+            .transactions
+            .iter()
+            .filter(|t| t.size.is_positive())
+            .map(|t| match self.side {
+                OrderSide::Bid => Amount {
+                    as_int: t.ask_gain.as_int / t.size.as_int,
+                },
+                OrderSide::Ask => Amount {
+                    as_int: t.bid_loss.as_int / t.size.as_int,
+                },
+            })
+            .collect();
+        opposite.extend(match self.side {
+            OrderSide::Bid => history.book.best_ask(),
+            OrderSide::Ask => history.book.best_bid(),
+        });
+
+        let best = match self.side {
+            OrderSide::Bid => opposite.into_iter().min(),
+            OrderSide::Ask => opposite.into_iter().max(),
+        };
+        self.prices
+            .iter()
+            .map(|&price| match (best, self.side) {
+                (Some(best), OrderSide::Bid) if price >= best => self.surplus(price),
+                (Some(best), OrderSide::Ask) if price <= best => self.surplus(price),
+                _ => 0.0,
+            })
+            .collect()
+    }
+}
+
+impl<T> Agent for LabTrader<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, rng: SimRng) {
+        self.rng = rng;
+    }
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        if let Some((chosen, before)) = self.last.take() {
+            // what the fill earned, at the price it went through
+            let payoff = (account.equity(self.valuation) - before.equity(self.valuation)).as_int;
+            let payoff = payoff as f64;
+
+            let mut payoffs = self.foregone(history);
+            match &mut self.model {
+                Model::RothErev(model) => model.learn(chosen, payoff),
+                Model::Ewa(model) => {
+                    payoffs[chosen] = payoff;
+                    model.learn(chosen, &payoffs);
+                }
+            }
+        }
+
+        let chosen = draw(&self.probabilities(), &mut self.rng);
+        self.last = Some((chosen, *account));
+        vec![OrderData {
+            side: self.side,
+            price: Some(self.prices[chosen]),
+            size: self.size,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::argmax,
+        test_market::{cents, from_producers},
+    };
+
+    #[test]
+    fn models_update_as_published() {
+        let mut roth_erev = RothErev::new(3, 10.0);
+        roth_erev.learn(0, 20.0);
+        // 0.9 * 10 + 0.8 * 20 for the chosen one, 0.9 * 10 + 0.1 * 20 for the others
+        assert_eq!(roth_erev.propensities, vec![25.0, 11.0, 11.0]);
+        let probabilities = roth_erev.probabilities();
+        assert!((probabilities[0] - 25.0 / 47.0).abs() < 1e-12);
+
+        let mut ewa = Ewa::new(2);
+        ewa.delta = 0.0;
+        ewa.learn(1, &[30.0, 10.0]);
+        // experience 0.9 + 1, foregone payoffs ignored
+        assert_eq!(ewa.experience, 1.9);
+        assert_eq!(ewa.attractions, vec![0.0, 10.0 / 1.9]);
+
+        ewa.delta = 1.0;
+        ewa.learn(1, &[30.0, 10.0]);
+        assert!(ewa.attractions[0] > ewa.attractions[1]);
+        assert!(ewa.probabilities()[0] > 0.5);
+    }
+
+    // the probabilities the buyer ends up with
+    fn buy(buyer: LabTrader<()>, seed: u64) -> Vec<f64> {
+        // the trader is shared with the run to look at it afterwards
+        let buyer = std::rc::Rc::new(std::cell::RefCell::new(buyer));
+        from_producers(Box::new(Shared(buyer.clone())), seed);
+
+        buyer.borrow().probabilities()
+    }
+
+    struct Shared(std::rc::Rc<std::cell::RefCell<LabTrader<()>>>);
+
+    impl Agent for Shared {
+        type CommodityType = ();
+
+        fn setup(&mut self, id: AgentId, info: &Self::MarketInfoType, rng: SimRng) {
+            self.0.borrow_mut().setup(id, info, rng);
+        }
+
+        fn produce_orders(
+            &mut self,
+            account: &Account,
+            info: &Self::MarketInfoType,
+            history: &History,
+        ) -> Vec<OrderData> {
+            self.0.borrow_mut().produce_orders(account, info, history)
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "needs prices")]
+    fn traders_need_a_price_grid() {
+        LabTrader::<()>::roth_erev(OrderSide::Bid, vec![], Amount { as_int: 100 });
+    }
+
+    #[test]
+    fn traders_settle_on_the_cheapest_price_that_trades() {
+        let prices = || cents(&[40, 60, 80, 100]);
+        let value = Amount { as_int: 100 };
+
+        let roth_erev = buy(LabTrader::roth_erev(OrderSide::Bid, prices(), value), 1);
+        let ewa = buy(LabTrader::ewa(OrderSide::Bid, prices(), value), 1);
+        for probabilities in [roth_erev, ewa] {
+            assert_eq!(argmax(&probabilities), 1, "{probabilities:?}");
+            assert!(probabilities[1] > 0.6, "{probabilities:?}");
+        }
+
+        assert_eq!(
+            buy(LabTrader::ewa(OrderSide::Bid, prices(), value), 2),
+            buy(LabTrader::ewa(OrderSide::Bid, prices(), value), 2)
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::chartist::{Aggressiveness, PositionLimit, price_series};
+
+/// Trades towards the fundamental value the history reports. It sees the value with
+/// normal noise of `noise` cents drawn from its own stream, buys when its estimate is
+/// more than `threshold_bps` above the last price of a unit and sells when it is as
+/// far below. Limit orders go in at the estimate, `offset` short of it. Before
+/// anything traded it picks a side at random. Its position stays within `limit`.
+pub struct Fundamentalist<T> {
+    pub noise: f64,
+    pub threshold_bps: i64,
+    pub size: Quantity,
+    pub limit: PositionLimit,
+    pub aggressiveness: Aggressiveness,
+
+    rng: SimRng,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> Fundamentalist<T> {
+    pub fn new(size: Quantity, max_position: Quantity) -> Self {
+        Self {
+            noise: 0.0,
+            threshold_bps: 0,
+            size,
+            limit: PositionLimit::new(max_position),
+            aggressiveness: Aggressiveness::Limit {
+                offset: Amount::new(),
+            },
+            rng: SimRng::seed_from_u64(0),
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_noise(mut self, noise: f64) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_threshold_bps(mut self, threshold_bps: i64) -> Self {
+        self.threshold_bps = threshold_bps;
+        self
+    }
+
+    pub fn with_aggressiveness(mut self, aggressiveness: Aggressiveness) -> Self {
+        self.aggressiveness = aggressiveness;
+        self
+    }
+
+    // what it takes the value to be this step
+    pub fn estimate(&mut self, history: &History) -> Option<f64> {
+        let value = history.fundamental?.as_int as f64;
+        Some(value + self.noise * self.rng.standard_normal())
+    }
+}
+
+impl<T> Agent for Fundamentalist<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, rng: SimRng) {
+        self.rng = rng;
+    }
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.limit.observe(account);
+        let Some(estimate) = self.estimate(history) else {
+            return vec![];
+        };
+
+        let side = match price_series(history).last() {
+            Some(&last) => {
+                let threshold = last * self.threshold_bps as f64 / 10_000.0;
+                if estimate > last + threshold {
+                    OrderSide::Bid
+                } else if estimate < last - threshold {
+                    OrderSide::Ask
+                } else {
+                    return vec![];
+                }
+            }
+            // without a trade yet it quotes its estimate to either side
+            None => *self.rng.choose(&[OrderSide::Bid, OrderSide::Ask]).unwrap(),
+        };
+
+        let size = self.size.min(self.limit.room(account, side));
+        if !size.is_positive() {
+            return vec![];
+        }
+
+        // short of the estimate rather than beyond it
+        let aggressiveness = match self.aggressiveness {
+            Aggressiveness::Limit { offset } => Aggressiveness::Limit { offset: -offset },
+            market => market,
+        };
+        let estimate = Amount {
+            as_int: estimate.round() as i64,
+        };
+        aggressiveness
+            .order(side, size, estimate)
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        market_maker::MarketMaker,
+        test_market::{TestAgent, WINDOW, market, run, traded_lots_at},
+    };
+    use market::{
+        fundamental::{Fundamental, ValueProcess},
+        market::MarketInfo,
+    };
+
+    type Trader = Fundamentalist<()>;
+
+    #[test]
+    fn trades_towards_the_value() {
+        let info = MarketInfo {
+            name: "fundamentalist".to_owned(),
+            commodity: (),
+        };
+        let account = Account {
+            commodity: Quantity::new(10),
+            ..Default::default()
+        };
+        // last traded at 100 a unit in lots of two
+        let mut history = traded_lots_at(&[100, 100], Quantity::new(2), WINDOW);
+        let mut trader = Trader::new(Quantity::new(2), Quantity::new(5)).with_threshold_bps(500);
+
+        // nothing to go on, then a value inside the threshold
+        assert!(trader.produce_orders(&account, &info, &history).is_empty());
+        history.fundamental = Some(Amount { as_int: 104 });
+        assert!(trader.produce_orders(&account, &info, &history).is_empty());
+
+        history.fundamental = Some(Amount { as_int: 120 });
+        let bid = trader.produce_orders(&account, &info, &history);
+        assert_eq!(bid[0].side, OrderSide::Bid);
+        assert_eq!(bid[0].price, Some(Amount { as_int: 120 }));
+
+        history.fundamental = Some(Amount { as_int: 80 });
+        let ask = trader.produce_orders(&account, &info, &history);
+        assert_eq!(ask[0].side, OrderSide::Ask);
+
+        // noise comes from the trader's own stream
+        let mut noisy = Trader::new(Quantity::new(1), Quantity::new(5)).with_noise(10.0);
+        let mut twin = Trader::new(Quantity::new(1), Quantity::new(5)).with_noise(10.0);
+        let estimates: Vec<_> = (0..5).map(|_| noisy.estimate(&history).unwrap()).collect();
+        assert_eq!(
+            estimates,
+            (0..5)
+                .map(|_| twin.estimate(&history).unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert!(estimates.iter().any(|&x| x != 80.0));
+    }
+
+    #[test]
+    fn prices_follow_a_jump_in_value() {
+        let fundamental = Fundamental::new(
+            ValueProcess::Jumps {
+                schedule: vec![(30, Amount { as_int: 150 })],
+            },
+            Amount { as_int: 100 },
+        );
+        let mut market = market(4).with_fundamental(fundamental);
+
+        let maker = MarketMaker::new(Amount { as_int: 2 }, Quantity::new(2), Quantity::new(50));
+        let agents = std::iter::once(Box::new(maker) as TestAgent)
+            .chain((0..6).map(|_| {
+                let trader = Trader::new(Quantity::new(2), Quantity::new(20)).with_noise(3.0);
+                Box::new(trader) as TestAgent
+            }))
+            .collect();
+        let (_, histories) = run(&mut market, agents, 100);
+
+        let average = |steps: &[History]| {
+            let prices: Vec<i64> = steps
+                .iter()
+                .flat_map(History::unit_price)
+                .map(|p| p.as_int)
+                .collect();
+            prices.iter().sum::<i64>() / prices.len() as i64
+        };
+        let early = average(&histories[20..29]);
+        assert!((95..=105).contains(&early), "{early}");
+        let late = average(&histories[90..]);
+        assert!((140..=160).contains(&late), "{late}");
+    }
+}
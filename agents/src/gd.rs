@@ -0,0 +1,338 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::schedule::Schedule;
+
+/// Prices seen in the recent steps, in cents per unit and counted by units.
+/// Traded quotes are taken, what was left in the book is rejected.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Belief {
+    pub taken_bids: Vec<(i64, i64)>,
+    pub taken_asks: Vec<(i64, i64)>,
+    pub rejected_bids: Vec<(i64, i64)>,
+    pub rejected_asks: Vec<(i64, i64)>,
+}
+
+fn count(quotes: &[(i64, i64)], include: impl Fn(i64) -> bool) -> f64 {
+    quotes
+        .iter()
+        .filter(|(price, _)| include(*price))
+        .map(|(_, units)| *units as f64)
+        .sum()
+}
+
+impl Belief {
+    // built from the last `steps` steps of the history and its window
+    pub fn from_history(history: &History, steps: usize) -> Self {
+        let mut belief = Belief::default();
+        let recent: Vec<&History> = history.recent().collect();
+
+        for step in &recent[recent.len().saturating_sub(steps)..] {
+            for trns in &step.transactions {
+                let units = trns.size.as_int;
+                if units <= 0 {
+                    continue;
+                }
+                belief
+                    .taken_bids
+                    .push((trns.bid_loss.as_int / units, units));
+                belief
+                    .taken_asks
+                    .push((trns.ask_gain.as_int / units, units));
+            }
+
+            let level = |(price, size): &(Amount, Quantity)| (price.as_int, size.as_int);
+            belief
+                .rejected_bids
+                .extend(step.book.bids.iter().map(level));
+            belief
+                .rejected_asks
+                .extend(step.book.asks.iter().map(level));
+        }
+
+        belief
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.taken_bids.is_empty() && self.rejected_bids.is_empty() && self.rejected_asks.is_empty()
+    }
+
+    // chance an ask at `price` trades: asks taken at or above it and bids that would
+    // have taken it, against asks left at or below it
+    pub fn ask_acceptance(&self, price: i64) -> f64 {
+        let taken = count(&self.taken_asks, |p| p >= price);
+        let bids =
+            count(&self.taken_bids, |p| p >= price) + count(&self.rejected_bids, |p| p >= price);
+        let rejected = count(&self.rejected_asks, |p| p <= price);
+
+        let total = taken + bids + rejected;
+        if total == 0.0 {
+            return 0.0;
+        }
+        (taken + bids) / total
+    }
+
+    // prices quoted or traded within `min..=max`, along with both ends
+    pub fn points(&self, min: i64, max: i64) -> Vec<i64> {
+        let quotes = [
+            &self.taken_bids,
+            &self.taken_asks,
+            &self.rejected_bids,
+            &self.rejected_asks,
+        ];
+        let mut points: Vec<i64> = (quotes.into_iter().flatten())
+            .map(|(price, _)| *price)
+            .filter(|price| (min..=max).contains(price))
+            .chain([min, max])
+            .collect();
+        points.sort();
+        points.dedup();
+        points
+    }
+
+    pub fn bid_acceptance(&self, price: i64) -> f64 {
+        let taken = count(&self.taken_bids, |p| p <= price);
+        let asks =
+            count(&self.taken_asks, |p| p <= price) + count(&self.rejected_asks, |p| p <= price);
+        let rejected = count(&self.rejected_bids, |p| p >= price);
+
+        let total = taken + asks + rejected;
+        if total == 0.0 {
+            return 0.0;
+        }
+        (taken + asks) / total
+    }
+}
+
+/// Gjerstad–Dickhaut trader. It estimates how likely a quote is to trade from the
+/// bids, asks and trades of the last `memory` steps, at the prices they name and
+/// linearly in between, and quotes the price with the largest expected surplus on
+/// its next unit. Without anything to go on it quotes
+/// at random within its value, like a constrained zero-intelligence trader.
+pub struct Gd<T> {
+    pub schedule: Schedule,
+    pub min_price: Amount,
+    pub max_price: Amount,
+    pub memory: usize,
+    pub size: Quantity,
+
+    rng: SimRng,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> Gd<T> {
+    pub fn new(side: OrderSide, values: Vec<Amount>, min_price: Amount, max_price: Amount) -> Self {
+        Self {
+            schedule: Schedule::new(side, values),
+            min_price,
+            max_price,
+            memory: 5,
+            size: Quantity::new(1),
+            rng: SimRng::seed_from_u64(0),
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_memory(mut self, steps: usize) -> Self {
+        self.memory = steps;
+        self
+    }
+
+    pub fn with_size(mut self, size: Quantity) -> Self {
+        self.size = size;
+        self
+    }
+
+    // prices the trader may quote for a unit of `value` without a loss
+    fn range(&self, value: Amount) -> (i64, i64) {
+        let (min, max) = match self.schedule.side {
+            OrderSide::Bid => (self.min_price, self.max_price.min(value)),
+            OrderSide::Ask => (self.min_price.max(value), self.max_price),
+        };
+        (min.as_int.max(1), max.as_int)
+    }
+
+    pub fn quote(&mut self, value: Amount, belief: &Belief) -> Option<Amount> {
+        let (min, max) = self.range(value);
+        if min > max {
+            return None;
+        }
+
+        let side = self.schedule.side;
+        let points: Vec<(i64, f64)> = (belief.points(min, max).into_iter())
+            .map(|price| match side {
+                OrderSide::Bid => (price, belief.bid_acceptance(price)),
+                OrderSide::Ask => (price, belief.ask_acceptance(price)),
+            })
+            .collect();
+
+        // between neighbouring points the surplus times the interpolated chance is
+        // a parabola, its peak is the only other price worth a look
+        let mut candidates = points.clone();
+        for pair in points.windows(2) {
+            let ((low, chance), (high, next)) = (pair[0], pair[1]);
+            let slope = (next - chance) / (high - low) as f64;
+            if slope == 0.0 {
+                continue;
+            }
+
+            let peak = (low + value.as_int) as f64 / 2.0 - chance / slope / 2.0;
+            for price in [peak.floor() as i64, peak.ceil() as i64] {
+                if low < price && price < high {
+                    candidates.push((price, chance + slope * (price - low) as f64));
+                }
+            }
+        }
+        candidates.sort_by_key(|(price, _)| *price);
+
+        // the lowest bid or the highest ask among the best ones
+        let mut best = (0.0, None);
+        for (price, chance) in candidates {
+            let surplus = match side {
+                OrderSide::Bid => (value.as_int - price) as f64 * chance,
+                OrderSide::Ask => (price - value.as_int) as f64 * chance,
+            };
+            let better = match side {
+                OrderSide::Bid => surplus > best.0,
+                OrderSide::Ask => surplus >= best.0 && surplus > 0.0,
+            };
+            if better {
+                best = (surplus, Some(price));
+            }
+        }
+
+        let price = match best.1 {
+            Some(price) if !belief.is_empty() => price,
+            _ => self.rng.range(min..=max),
+        };
+        Some(Amount { as_int: price })
+    }
+}
+
+impl<T> Agent for Gd<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, rng: SimRng) {
+        self.rng = rng;
+    }
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.schedule.observe(account, history.step);
+        let Some(value) = self.schedule.next_value(account) else {
+            return vec![];
+        };
+
+        let belief = Belief::from_history(history, self.memory);
+        let Some(price) = self.quote(value, &belief) else {
+            return vec![];
+        };
+
+        vec![OrderData {
+            side: self.schedule.side,
+            price: Some(price),
+            size: self.size.min(self.schedule.remaining(account)),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_market::{TestAgent, market, run, schedules};
+
+    type Trader = Gd<()>;
+
+    fn trader(side: OrderSide, values: Vec<Amount>) -> Trader {
+        Trader::new(side, values, Amount { as_int: 1 }, Amount { as_int: 200 })
+    }
+
+    #[test]
+    fn beliefs_are_monotone() {
+        let belief = Belief {
+            taken_bids: vec![(100, 2), (110, 1)],
+            taken_asks: vec![(95, 2), (105, 1)],
+            rejected_bids: vec![(80, 1), (90, 3)],
+            rejected_asks: vec![(120, 2), (130, 1)],
+        };
+
+        let asks: Vec<f64> = (50..=150).map(|p| belief.ask_acceptance(p)).collect();
+        let bids: Vec<f64> = (50..=150).map(|p| belief.bid_acceptance(p)).collect();
+        assert!(asks.windows(2).all(|w| w[0] >= w[1]));
+        assert!(bids.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(belief.ask_acceptance(50), 1.0);
+        assert_eq!(belief.bid_acceptance(150), 1.0);
+
+        // a seller with cost 1.00 asks above its cost and below the rejected asks
+        let mut seller = trader(OrderSide::Ask, vec![Amount { as_int: 100 }]);
+        let ask = seller.quote(Amount { as_int: 100 }, &belief).unwrap();
+        assert!((101..120).contains(&ask.as_int), "{ask}");
+    }
+
+    #[test]
+    fn quotes_between_the_observed_prices() {
+        // asks trade up to the 1.00 bid and fail from the 1.40 ask on
+        let belief = Belief {
+            taken_bids: vec![(100, 1)],
+            rejected_asks: vec![(140, 1)],
+            ..Default::default()
+        };
+        assert_eq!(belief.points(80, 200), [80, 100, 140, 200]);
+
+        // 0.30 surplus at 3/4 of a chance beats 0.20 for sure
+        let mut seller = trader(OrderSide::Ask, vec![Amount { as_int: 80 }]);
+        let ask = seller.quote(Amount { as_int: 80 }, &belief).unwrap();
+        assert_eq!(ask, Amount { as_int: 110 });
+    }
+
+    #[test]
+    fn prices_converge_to_the_equilibrium() {
+        let (buyers, sellers) = schedules();
+        let day = 10;
+        let agents = (buyers.into_iter().map(|v| (OrderSide::Bid, v)))
+            .chain(sellers.into_iter().map(|v| (OrderSide::Ask, v)))
+            .map(|(side, values)| {
+                let mut trader = trader(side, values).with_memory(10);
+                trader.schedule = trader.schedule.with_day_length(day);
+                Box::new(trader) as TestAgent
+            })
+            .collect();
+
+        let (_, histories) = run(&mut market(3), agents, 20 * day);
+
+        // root mean square distance of the prices from the 1.05 equilibrium
+        let day_prices = |n: usize| {
+            let trades: Vec<f64> = histories[n * day as usize..(n + 1) * day as usize]
+                .iter()
+                .flat_map(|h| &h.transactions)
+                .map(|t| (t.bid_loss + t.ask_gain).as_int as f64 / 2.0 / t.size.as_int as f64)
+                .collect();
+            let deviation = trades.iter().map(|p| (p - 105.0).powi(2)).sum::<f64>();
+            (trades.len(), (deviation / trades.len() as f64).sqrt())
+        };
+
+        // the first days trade well away from it, the last ones close to it
+        let mean_deviation = |days: std::ops::Range<usize>| {
+            let n = days.len() as f64;
+            days.map(|n| day_prices(n).1).sum::<f64>() / n
+        };
+        let (first, last) = (mean_deviation(0..3), mean_deviation(17..20));
+        assert!(last < first / 2.0, "{first} {last}");
+        assert!(last < 5.0, "{last}");
+
+        let (volume, _) = day_prices(19);
+        assert!((7..=10).contains(&volume), "{volume}");
+    }
+}
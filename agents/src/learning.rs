@@ -0,0 +1,505 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::{
+    chartist::{PositionLimit, price_series},
+    math::{argmax, ln},
+};
+
+/// Turns what an agent sees into one of `count()` states. Every feature splits into
+/// buckets at its edges: the last price (with an extra bucket before the first
+/// trade), the inventory held beyond the start and, when `trend` is set, whether
+/// the last price went up, down or neither. Without edges there is one state.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateSpace {
+    pub price_edges: Vec<Amount>,
+    pub inventory_edges: Vec<i64>,
+    pub trend: bool,
+}
+
+impl StateSpace {
+    pub fn count(&self) -> usize {
+        let trends = if self.trend { 3 } else { 1 };
+        (self.price_edges.len() + 2) * (self.inventory_edges.len() + 1) * trends
+    }
+
+    pub fn state(&self, history: &History, inventory: Quantity) -> usize {
+        let prices = price_series(history);
+
+        let price = match prices.last() {
+            None => 0,
+            Some(&last) => {
+                1 + self
+                    .price_edges
+                    .iter()
+                    .filter(|edge| last >= edge.as_int as f64)
+                    .count()
+            }
+        };
+        let inventory = self
+            .inventory_edges
+            .iter()
+            .filter(|&&edge| inventory.as_int >= edge)
+            .count();
+        let trend = match prices.as_slice() {
+            [.., before, last] if self.trend && last > before => 1,
+            [.., before, last] if self.trend && last < before => 2,
+            _ => 0,
+        };
+
+        let trends = if self.trend { 3 } else { 1 };
+        (price * (self.inventory_edges.len() + 1) + inventory) * trends + trend
+    }
+}
+
+/// How a bandit trades off trying actions against using the best one so far.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Exploration {
+    // a random action with probability `epsilon`, the best one otherwise
+    EpsilonGreedy { epsilon: f64 },
+    // the action with the highest upper confidence bound, `c` scales the bonus
+    Ucb { c: f64 },
+}
+
+/// Multi-armed bandit keeping the average reward of every action. It ignores the
+/// state, so it learns the single best action.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bandit {
+    pub exploration: Exploration,
+    pub values: Vec<f64>,
+    pub counts: Vec<u64>,
+}
+
+impl Bandit {
+    pub fn new(actions: usize, exploration: Exploration) -> Self {
+        Self {
+            exploration,
+            values: vec![0.0; actions],
+            counts: vec![0; actions],
+        }
+    }
+
+    pub fn epsilon_greedy(actions: usize, epsilon: f64) -> Self {
+        Self::new(actions, Exploration::EpsilonGreedy { epsilon })
+    }
+
+    pub fn ucb(actions: usize, c: f64) -> Self {
+        Self::new(actions, Exploration::Ucb { c })
+    }
+
+    pub fn choose(&self, rng: &mut SimRng) -> usize {
+        match self.exploration {
+            Exploration::EpsilonGreedy { epsilon } => {
+                if rng.chance(epsilon) {
+                    rng.below(self.values.len() as u64) as usize
+                } else {
+                    argmax(&self.values)
+                }
+            }
+            Exploration::Ucb { c } => {
+                // every action is tried once before the bounds mean anything
+                if let Some(untried) = self.counts.iter().position(|&n| n == 0) {
+                    return untried;
+                }
+                let log_total = ln(self.counts.iter().sum::<u64>() as f64);
+                let bounds: Vec<f64> = (self.values.iter().zip(&self.counts))
+                    .map(|(value, &n)| value + c * (log_total / n as f64).sqrt())
+                    .collect();
+                argmax(&bounds)
+            }
+        }
+    }
+
+    pub fn learn(&mut self, action: usize, reward: f64) {
+        self.counts[action] += 1;
+        self.values[action] += (reward - self.values[action]) / self.counts[action] as f64;
+    }
+}
+
+/// Tabular Q-learning with epsilon-greedy exploration.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QLearning {
+    pub learning_rate: f64,
+    pub discount: f64,
+    pub epsilon: f64,
+    // action values, a row of `actions` for every state
+    pub table: Vec<f64>,
+    pub actions: usize,
+}
+
+impl QLearning {
+    pub fn new(states: usize, actions: usize) -> Self {
+        Self {
+            learning_rate: 0.1,
+            discount: 0.9,
+            epsilon: 0.1,
+            table: vec![0.0; states * actions],
+            actions,
+        }
+    }
+
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn with_discount(mut self, discount: f64) -> Self {
+        self.discount = discount;
+        self
+    }
+
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    pub fn values(&self, state: usize) -> &[f64] {
+        &self.table[state * self.actions..(state + 1) * self.actions]
+    }
+
+    pub fn choose(&self, state: usize, rng: &mut SimRng) -> usize {
+        if rng.chance(self.epsilon) {
+            rng.below(self.actions as u64) as usize
+        } else {
+            argmax(self.values(state))
+        }
+    }
+
+    pub fn learn(&mut self, state: usize, action: usize, reward: f64, next: usize) {
+        let best_next = self.values(next)[argmax(self.values(next))];
+        let value = &mut self.table[state * self.actions + action];
+        *value += self.learning_rate * (reward + self.discount * best_next - *value);
+    }
+}
+
+/// What a learning agent has learned, everything that has to be kept to pick up
+/// where it left off.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Policy {
+    Bandit(Bandit),
+    QLearning(QLearning),
+}
+
+impl Policy {
+    pub fn choose(&self, state: usize, rng: &mut SimRng) -> usize {
+        match self {
+            Policy::Bandit(bandit) => bandit.choose(rng),
+            Policy::QLearning(q) => q.choose(state, rng),
+        }
+    }
+
+    pub fn learn(&mut self, state: usize, action: usize, reward: f64, next: usize) {
+        match self {
+            Policy::Bandit(bandit) => bandit.learn(action, reward),
+            Policy::QLearning(q) => q.learn(state, action, reward, next),
+        }
+    }
+
+    // the action it takes once it stops exploring
+    pub fn greedy(&self, state: usize) -> usize {
+        match self {
+            Policy::Bandit(bandit) => argmax(&bandit.values),
+            Policy::QLearning(q) => argmax(q.values(state)),
+        }
+    }
+}
+
+// the state an action was taken in and the account it was taken with
+#[derive(Clone, Debug)]
+struct Taken {
+    state: usize,
+    action: usize,
+    account: Account,
+}
+
+/// Places one of its `actions` every step and learns from the change in its equity
+/// until the next one. Equity is marked at `valuation` when the agent has a value
+/// of its own for the commodity and at the last price of a unit otherwise.
+pub struct Learner<T> {
+    pub states: StateSpace,
+    pub actions: Vec<OrderData>,
+    pub policy: Policy,
+    pub valuation: Option<Amount>,
+
+    rng: SimRng,
+    position: PositionLimit,
+    last: Option<Taken>,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> Learner<T> {
+    pub fn new(states: StateSpace, actions: Vec<OrderData>, policy: Policy) -> Self {
+        Self {
+            states,
+            actions,
+            policy,
+            valuation: None,
+            rng: SimRng::seed_from_u64(0),
+            position: PositionLimit::unlimited(),
+            last: None,
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn epsilon_greedy(actions: Vec<OrderData>, epsilon: f64) -> Self {
+        let bandit = Bandit::epsilon_greedy(actions.len(), epsilon);
+        Self::new(StateSpace::default(), actions, Policy::Bandit(bandit))
+    }
+
+    pub fn ucb(actions: Vec<OrderData>, c: f64) -> Self {
+        let bandit = Bandit::ucb(actions.len(), c);
+        Self::new(StateSpace::default(), actions, Policy::Bandit(bandit))
+    }
+
+    pub fn q_learning(states: StateSpace, actions: Vec<OrderData>) -> Self {
+        let q = QLearning::new(states.count(), actions.len());
+        Self::new(states, actions, Policy::QLearning(q))
+    }
+
+    pub fn with_valuation(mut self, valuation: Amount) -> Self {
+        self.valuation = Some(valuation);
+        self
+    }
+
+    // continues from a policy learned over the same states and actions
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        let (states, actions) = (self.states.count(), self.actions.len());
+        let fits = match &policy {
+            Policy::Bandit(bandit) => {
+                bandit.values.len() == actions && bandit.counts.len() == actions
+            }
+            Policy::QLearning(q) => q.actions == actions && q.table.len() == states * actions,
+        };
+        assert!(
+            fits,
+            "{:?} doesn't fit {} states and {} actions",
+            policy, states, actions
+        );
+        self.policy = policy;
+        self
+    }
+
+    // limit orders of `size` on `side` at each of `prices`
+    pub fn price_actions(side: OrderSide, prices: &[Amount], size: Quantity) -> Vec<OrderData> {
+        prices
+            .iter()
+            .map(|&price| OrderData {
+                side,
+                price: Some(price),
+                size,
+            })
+            .collect()
+    }
+}
+
+impl<T> Agent for Learner<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, rng: SimRng) {
+        self.rng = rng;
+    }
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.position.observe(account);
+        let state = self.states.state(history, self.position.position(account));
+
+        if let Some(last) = self.last.take() {
+            let mark = self
+                .valuation
+                .or_else(|| history.recent().flat_map(History::unit_price).last())
+                .unwrap_or_default();
+            let reward = (account.equity(mark) - last.account.equity(mark)).as_int as f64;
+            self.policy.learn(last.state, last.action, reward, state);
+        }
+
+        let action = self.policy.choose(state, &mut self.rng);
+        self.last = Some(Taken {
+            state,
+            action,
+            account: *account,
+        });
+        vec![self.actions[action]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_market::{WINDOW, from_producers, traded_at, traded_lots_at};
+    use market::market::MarketInfo;
+
+    #[test]
+    fn bandits_find_the_best_arm() {
+        // arm 2 pays most on average
+        let means = [1.0, 2.0, 3.0, 2.5];
+        for mut bandit in [Bandit::epsilon_greedy(4, 0.1), Bandit::ucb(4, 2.0)] {
+            let mut rng = SimRng::seed_from_u64(9);
+            let mut pulls = [0; 4];
+            for _ in 0..2000 {
+                let arm = bandit.choose(&mut rng);
+                pulls[arm] += 1;
+                bandit.learn(arm, means[arm] + rng.standard_normal());
+            }
+            assert_eq!(argmax(&bandit.values), 2, "{bandit:?}");
+            assert!(pulls[2] > 1000, "{pulls:?}");
+        }
+    }
+
+    #[test]
+    fn q_learning_learns_a_policy_per_state() {
+        // two states, the action that pays differs and action 1 moves to the other state
+        let mut q = QLearning::new(2, 2).with_epsilon(0.2);
+        let mut rng = SimRng::seed_from_u64(3);
+        let mut state = 0;
+        for _ in 0..5000 {
+            let action = q.choose(state, &mut rng);
+            let reward = if action == state { 1.0 } else { 0.0 };
+            let next = if action == 1 { 1 - state } else { state };
+            q.learn(state, action, reward, next);
+            state = next;
+        }
+        let policy = Policy::QLearning(q);
+        assert_eq!((policy.greedy(0), policy.greedy(1)), (0, 1));
+    }
+
+    #[test]
+    fn states_bucket_the_features() {
+        let states = StateSpace {
+            price_edges: vec![Amount { as_int: 100 }],
+            inventory_edges: vec![0],
+            trend: true,
+        };
+        assert_eq!(states.count(), 18);
+
+        let history = traded_at(&[90, 120], WINDOW);
+        let all: Vec<usize> = [-1, 3]
+            .iter()
+            .map(|&units| states.state(&history, Quantity::new(units)))
+            .chain([states.state(&History::default(), Quantity::new(0))])
+            .collect();
+        assert_eq!(all, vec![(2 * 2) * 3 + 1, (2 * 2 + 1) * 3 + 1, 3]);
+        assert!(all.iter().all(|&state| state < states.count()));
+    }
+
+    #[test]
+    fn larger_fills_are_read_per_unit() {
+        let states = StateSpace {
+            price_edges: vec![Amount { as_int: 100 }],
+            ..Default::default()
+        };
+        let lots = traded_lots_at(&[90, 90], Quantity::new(3), WINDOW);
+        assert_eq!(states.state(&lots, Quantity::ZERO), 1);
+
+        // a unit bought at the price of a unit earns nothing
+        let info = MarketInfo {
+            name: "learning".to_owned(),
+            commodity: (),
+        };
+        let mut learner = Learner::<()>::epsilon_greedy(bids(), 0.0);
+        let before = Account {
+            commodity: Quantity::new(10),
+            money: Amount { as_int: 1000 },
+            ..Default::default()
+        };
+        let after = Account {
+            commodity: Quantity::new(11),
+            money: Amount { as_int: 910 },
+            ..before
+        };
+        learner.produce_orders(&before, &info, &lots);
+        learner.produce_orders(&after, &info, &lots);
+
+        let Policy::Bandit(bandit) = &learner.policy else {
+            unreachable!();
+        };
+        assert_eq!(bandit.values[0], 0.0);
+        assert_eq!(bandit.counts[0], 1);
+    }
+
+    // what the buyer valuing units at 1.00 bid in every late step that traded
+    fn buy(buyer: Learner<()>, seed: u64) -> Vec<i64> {
+        let buyer = buyer.with_valuation(Amount { as_int: 100 });
+        let histories = from_producers(Box::new(buyer), seed);
+        histories
+            .iter()
+            .skip(200)
+            .flat_map(|h| &h.transactions)
+            .map(|t| t.bid_loss.as_int)
+            .collect()
+    }
+
+    fn bids() -> Vec<OrderData> {
+        let prices = [40, 60, 80, 100].map(|as_int| Amount { as_int });
+        Learner::<()>::price_actions(OrderSide::Bid, &prices, Quantity::new(1))
+    }
+
+    #[test]
+    fn buyers_learn_the_best_price() {
+        let states = StateSpace {
+            price_edges: vec![Amount { as_int: 70 }],
+            ..Default::default()
+        };
+        let q = Learner::q_learning(states, bids())
+            .with_policy(Policy::QLearning(QLearning::new(3, 4).with_discount(0.0)));
+
+        // 0.60 is the cheapest bid that still trades
+        for buyer in [
+            Learner::epsilon_greedy(bids(), 0.1),
+            Learner::ucb(bids(), 5.0),
+            q,
+        ] {
+            let paid = buy(buyer, 1);
+            let best = paid.iter().filter(|&&price| price == 60).count();
+            assert!(best > 80, "{paid:?}");
+        }
+
+        assert_eq!(
+            buy(Learner::epsilon_greedy(bids(), 0.3), 2),
+            buy(Learner::epsilon_greedy(bids(), 0.3), 2)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn policies_have_to_fit_the_states() {
+        let states = StateSpace {
+            trend: true,
+            ..Default::default()
+        };
+        Learner::<()>::q_learning(states, bids())
+            .with_policy(Policy::QLearning(QLearning::new(2, 4)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn policies_survive_serialisation() {
+        let mut q = QLearning::new(3, 2);
+        q.learn(1, 1, 5.0, 2);
+        let mut bandit = Bandit::ucb(2, 1.0);
+        bandit.learn(0, 2.0);
+
+        for policy in [Policy::QLearning(q), Policy::Bandit(bandit)] {
+            let json = serde_json::to_string(&policy).unwrap();
+            let loaded: Policy = serde_json::from_str(&json).unwrap();
+            assert_eq!(loaded, policy);
+        }
+    }
+}
@@ -1,9 +1,22 @@
 use std::vec;
 
+pub mod chartist;
+pub mod experimental;
+pub mod fundamentalist;
+pub mod gd;
+pub mod learning;
+pub mod market_maker;
+mod math;
+pub mod production;
+pub mod schedule;
+pub mod zero_intelligence;
+pub mod zip;
+
 use market::{
     agent::{Agent, AgentId},
     amount::Amount,
     orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
 };
 
 pub struct IdleAgent<T> {
@@ -13,7 +26,13 @@ pub struct IdleAgent<T> {
 impl<T> Agent for IdleAgent<T> {
     type CommodityType = T;
 
-    fn setup(&mut self, _id: market::agent::AgentId, _info: &Self::MarketInfoType) {}
+    fn setup(
+        &mut self,
+        _id: market::agent::AgentId,
+        _info: &Self::MarketInfoType,
+        _rng: market::rng::SimRng,
+    ) {
+    }
 
     fn produce_orders(
         &mut self,
@@ -26,7 +45,7 @@ impl<T> Agent for IdleAgent<T> {
 }
 
 pub struct SellAgent<T> {
-    pub ask_size: i64,
+    pub ask_size: Quantity,
     pub ask_amount: i64,
     pub period: u64,
     pub innate_price: Option<Amount>,
@@ -36,7 +55,13 @@ pub struct SellAgent<T> {
 impl<T> Agent for SellAgent<T> {
     type CommodityType = T;
 
-    fn setup(&mut self, _id: market::agent::AgentId, _info: &Self::MarketInfoType) {}
+    fn setup(
+        &mut self,
+        _id: market::agent::AgentId,
+        _info: &Self::MarketInfoType,
+        _rng: market::rng::SimRng,
+    ) {
+    }
 
     fn produce_orders(
         &mut self,
@@ -44,7 +69,7 @@ impl<T> Agent for SellAgent<T> {
         _info: &Self::MarketInfoType,
         history: &market::market::History,
     ) -> Vec<market::orders::flat::OrderData> {
-        if account.commodity == 0 || history.step % self.period != 0 {
+        if account.commodity == Quantity::ZERO || !history.step.is_multiple_of(self.period) {
             return vec![];
         }
 
@@ -61,7 +86,7 @@ impl<T> Agent for SellAgent<T> {
 
 #[derive(Clone, Debug)]
 pub struct BuyAgent<T> {
-    pub bid_size: i64,
+    pub bid_size: Quantity,
     pub bid_amount: i64,
     pub period: u64,
     pub innate_price: Option<Amount>,
@@ -71,7 +96,13 @@ pub struct BuyAgent<T> {
 impl<T> Agent for BuyAgent<T> {
     type CommodityType = T;
 
-    fn setup(&mut self, _id: market::agent::AgentId, _info: &Self::MarketInfoType) {}
+    fn setup(
+        &mut self,
+        _id: market::agent::AgentId,
+        _info: &Self::MarketInfoType,
+        _rng: market::rng::SimRng,
+    ) {
+    }
 
     fn produce_orders(
         &mut self,
@@ -79,7 +110,7 @@ impl<T> Agent for BuyAgent<T> {
         _info: &Self::MarketInfoType,
         history: &market::market::History,
     ) -> Vec<market::orders::flat::OrderData> {
-        if account.money.as_int == 0 || history.step % self.period != 0 {
+        if account.money.as_int == 0 || !history.step.is_multiple_of(self.period) {
             return vec![];
         }
 
@@ -95,7 +126,7 @@ impl<T> Agent for BuyAgent<T> {
 }
 
 pub struct IncBuyAgent<T> {
-    pub bid_size: i64,
+    pub bid_size: Quantity,
     pub bid_amount: i64,
     pub period: u64,
 
@@ -110,7 +141,12 @@ pub struct IncBuyAgent<T> {
 impl<T> Agent for IncBuyAgent<T> {
     type CommodityType = T;
 
-    fn setup(&mut self, id: market::agent::AgentId, _info: &Self::MarketInfoType) {
+    fn setup(
+        &mut self,
+        id: market::agent::AgentId,
+        _info: &Self::MarketInfoType,
+        _rng: market::rng::SimRng,
+    ) {
         self.my_id = id;
     }
 
@@ -120,7 +156,7 @@ impl<T> Agent for IncBuyAgent<T> {
         _info: &Self::MarketInfoType,
         history: &market::market::History,
     ) -> Vec<OrderData> {
-        if history.step % self.period != 0 {
+        if !history.step.is_multiple_of(self.period) {
             return vec![];
         }
 
@@ -128,14 +164,14 @@ impl<T> Agent for IncBuyAgent<T> {
             return vec![];
         }
 
-        let missing_comm: i64 = history
+        let missing_comm: Quantity = history
             .filter_by_agent_id(&self.my_id)
             .unfulfilled_orders
             .iter()
             .map(|(_, o)| o.size)
             .sum();
 
-        match missing_comm.cmp(&0) {
+        match missing_comm.cmp(&Quantity::ZERO) {
             std::cmp::Ordering::Greater => {
                 self.price = Amount {
                     as_int: (self.price.as_int + self.increment.as_int).max(0),
@@ -161,7 +197,7 @@ impl<T> Agent for IncBuyAgent<T> {
             return vec![];
         };
 
-        let order_num = (units / self.bid_size).min(self.bid_amount);
+        let order_num = (units / self.bid_size.as_int).min(self.bid_amount);
 
         if order_num <= 0 {
             return vec![];
@@ -179,7 +215,7 @@ impl<T> Agent for IncBuyAgent<T> {
 }
 
 pub struct IncSellAgent<T> {
-    pub ask_size: i64,
+    pub ask_size: Quantity,
     pub ask_amount: i64,
     pub period: u64,
 
@@ -194,7 +230,12 @@ pub struct IncSellAgent<T> {
 impl<T> Agent for IncSellAgent<T> {
     type CommodityType = T;
 
-    fn setup(&mut self, id: market::agent::AgentId, _info: &Self::MarketInfoType) {
+    fn setup(
+        &mut self,
+        id: market::agent::AgentId,
+        _info: &Self::MarketInfoType,
+        _rng: market::rng::SimRng,
+    ) {
         self.my_id = id;
     }
 
@@ -204,22 +245,22 @@ impl<T> Agent for IncSellAgent<T> {
         _info: &Self::MarketInfoType,
         history: &market::market::History,
     ) -> Vec<OrderData> {
-        if history.step % self.period != 0 {
+        if !history.step.is_multiple_of(self.period) {
             return vec![];
         }
 
-        if account.commodity == 0 {
+        if account.commodity == Quantity::ZERO {
             return vec![];
         }
 
-        let missing_comm: i64 = history
+        let missing_comm: Quantity = history
             .filter_by_agent_id(&self.my_id)
             .unfulfilled_orders
             .iter()
             .map(|(_, o)| o.size)
             .sum();
 
-        match missing_comm.cmp(&0) {
+        match missing_comm.cmp(&Quantity::ZERO) {
             std::cmp::Ordering::Greater => {
                 self.price = Amount {
                     as_int: (self.price.as_int - self.increment.as_int).max(0),
@@ -239,7 +280,7 @@ impl<T> Agent for IncSellAgent<T> {
             ];
         }
 
-        let order_num = (account.commodity / self.ask_size).min(self.ask_amount);
+        let order_num = (account.commodity.as_int / self.ask_size.as_int).min(self.ask_amount);
 
         if order_num <= 0 {
             return vec![];
@@ -255,3 +296,161 @@ impl<T> Agent for IncSellAgent<T> {
         ]
     }
 }
+
+#[cfg(test)]
+mod test_market {
+    use std::cell::RefCell;
+
+    use market::{
+        account::Account,
+        agent::{Agent, AgentId},
+        amount::Amount,
+        market::{History, Market, MarketInfo},
+        order_book::Transaction,
+        quantity::Quantity,
+    };
+
+    use crate::production::{Cost, Producer};
+
+    pub type TestAgent = Box<dyn Agent<CommodityType = (), MarketInfoType = MarketInfo<()>>>;
+
+    // every trader starts with enough of both to go through its schedules many times
+    pub const COMMODITY: i64 = 100;
+    pub const MONEY: i64 = 1000;
+    // earlier steps every history carries
+    pub const WINDOW: usize = 20;
+
+    pub fn cents(values: &[i64]) -> Vec<Amount> {
+        values.iter().map(|&as_int| Amount { as_int }).collect()
+    }
+
+    // buyer and seller schedules crossing between 1.00 and 1.10, nine units trade there
+    pub fn schedules() -> (Vec<Vec<Amount>>, Vec<Vec<Amount>>) {
+        let buyers = vec![
+            cents(&[190, 150, 110]),
+            cents(&[180, 140, 100]),
+            cents(&[170, 130, 90]),
+            cents(&[160, 120, 80]),
+        ];
+        let sellers = vec![
+            cents(&[20, 60, 100]),
+            cents(&[30, 70, 110]),
+            cents(&[40, 80, 120]),
+            cents(&[50, 90, 130]),
+        ];
+        (buyers, sellers)
+    }
+
+    // a history whose steps traded a unit each at `prices`, keeping `window` earlier steps
+    pub fn traded_at(prices: &[i64], window: usize) -> History {
+        traded_lots_at(prices, Quantity::new(1), window)
+    }
+
+    // like `traded_at` with fills of `size` units at `prices` a unit
+    pub fn traded_lots_at(prices: &[i64], size: Quantity, window: usize) -> History {
+        prices
+            .iter()
+            .enumerate()
+            .fold(History::default(), |history, (step, &price)| {
+                let next = History {
+                    step: step as u64 + 1,
+                    transactions: vec![Transaction {
+                        size,
+                        bid_loss: Amount {
+                            as_int: price * size.as_int,
+                        },
+                        ask_gain: Amount {
+                            as_int: price * size.as_int,
+                        },
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                };
+                history.advance(next, window)
+            })
+    }
+
+    pub fn market(seed: u64) -> Market<()> {
+        Market::new(MarketInfo {
+            name: "agents".to_owned(),
+            commodity: (),
+        })
+        .with_seed(seed)
+    }
+
+    pub fn endowment() -> Account {
+        Account {
+            commodity: Quantity::new(COMMODITY),
+            money: Amount::units(MONEY),
+            ..Default::default()
+        }
+    }
+
+    // runs a buyer against four producers asking 0.50 for a unit every step
+    pub fn from_producers(buyer: TestAgent, seed: u64) -> Vec<History> {
+        let producer = || {
+            let cost = Cost::Constant {
+                cost: Amount { as_int: 50 },
+            };
+            Box::new(Producer::<()>::new(cost, Quantity::new(1)).with_period(1)) as TestAgent
+        };
+
+        let agents = std::iter::once(buyer)
+            .chain((0..4).map(|_| producer()))
+            .collect();
+        run(&mut market(seed), agents, 300).1
+    }
+
+    // registers the traders with the endowment and runs them, see `run_with`
+    pub fn run(
+        market: &mut Market<()>,
+        traders: Vec<TestAgent>,
+        steps: u64,
+    ) -> (Vec<AgentId>, Vec<History>) {
+        let traders = traders.into_iter().map(|agent| (agent, endowment()));
+        run_with(market, traders.collect(), steps)
+    }
+
+    // registers the traders with their accounts and runs them,
+    // returns their ids and the history of every step
+    pub fn run_with(
+        market: &mut Market<()>,
+        traders: Vec<(TestAgent, Account)>,
+        steps: u64,
+    ) -> (Vec<AgentId>, Vec<History>) {
+        let agents: Vec<(AgentId, RefCell<TestAgent>)> = traders
+            .into_iter()
+            .map(|(mut agent, account)| {
+                let id = market.register_with_acc(account);
+                agent.setup(id, &market.info, market.agent_rng(id));
+                (id, RefCell::new(agent))
+            })
+            .collect();
+
+        let mut history = History::default();
+        let mut histories = Vec::new();
+        for step in 1..=steps {
+            market.start_step(step);
+            let rejected_orders = market.agents_submit_orders(&agents, &history);
+            let transactions = market.process_submitted_orders(history.market_price());
+            let unfulfilled_orders = market.all_orders();
+            let book = market.book_snapshot();
+            let fundamental = market.fundamental();
+            market.clear_reserves_and_orders();
+
+            let next = History {
+                step,
+                transactions,
+                rejected_orders,
+                unfulfilled_orders,
+                book,
+                fundamental,
+                ..Default::default()
+            };
+            history = history.advance(next, WINDOW);
+            histories.push(history.clone());
+        }
+
+        (agents.into_iter().map(|(id, _)| id).collect(), histories)
+    }
+}
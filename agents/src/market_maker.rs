@@ -0,0 +1,261 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::chartist::{PositionLimit, price_series};
+
+/// Two-sided liquidity provider in the manner of Avellaneda and Stoikov. Every step it
+/// quotes a fresh bid and ask around a reservation price: the fair value moved against
+/// the inventory it holds beyond its target, by `risk_aversion` times the variance of
+/// recent prices per unit. The half spread is `half_spread` plus `volatility_factor`
+/// times the standard deviation of those prices. Its inventory is counted from what
+/// it held at the first step and never leaves `limit`.
+///
+/// Markets that clear the book between steps cancel the quotes of the last step, so
+/// posting new ones replaces them.
+pub struct MarketMaker<T> {
+    // quotes around this when given, around the book or the last price otherwise
+    pub fair_value: Option<Amount>,
+    pub half_spread: Amount,
+    pub size: Quantity,
+    pub limit: PositionLimit,
+    pub risk_aversion: f64,
+    pub volatility_factor: f64,
+    // steps of the history window the volatility is measured over
+    pub memory: usize,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> MarketMaker<T> {
+    pub fn new(half_spread: Amount, size: Quantity, max_inventory: Quantity) -> Self {
+        Self {
+            fair_value: None,
+            half_spread,
+            size,
+            limit: PositionLimit::new(max_inventory),
+            risk_aversion: 0.0,
+            volatility_factor: 0.0,
+            memory: 10,
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_fair_value(mut self, fair_value: Amount) -> Self {
+        self.fair_value = Some(fair_value);
+        self
+    }
+
+    pub fn with_risk_aversion(mut self, risk_aversion: f64) -> Self {
+        self.risk_aversion = risk_aversion;
+        self
+    }
+
+    pub fn with_volatility_factor(mut self, volatility_factor: f64) -> Self {
+        self.volatility_factor = volatility_factor;
+        self
+    }
+
+    pub fn with_memory(mut self, steps: usize) -> Self {
+        self.memory = steps;
+        self
+    }
+
+    pub fn inventory(&self, account: &Account) -> Quantity {
+        self.limit.position(account)
+    }
+
+    fn fair_value(&self, history: &History) -> Option<f64> {
+        if let Some(value) = self.fair_value {
+            return Some(value.as_int as f64);
+        }
+
+        let book = &history.book;
+        match (book.best_bid(), book.best_ask()) {
+            (Some(bid), Some(ask)) => Some((bid + ask).as_int as f64 / 2.0),
+            _ => history.unit_price().map(|price| price.as_int as f64),
+        }
+    }
+
+    // standard deviation of the step to step change of the last prices
+    pub fn volatility(&self, history: &History) -> f64 {
+        let prices = price_series(history);
+        let changes: Vec<f64> = prices[prices.len().saturating_sub(self.memory + 1)..]
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .collect();
+
+        if changes.is_empty() {
+            return 0.0;
+        }
+        let mean = changes.iter().sum::<f64>() / changes.len() as f64;
+        let variance =
+            changes.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / changes.len() as f64;
+        variance.sqrt()
+    }
+
+    // bid and ask for the next step, either may be missing at the inventory cap
+    pub fn quotes(&self, account: &Account, history: &History) -> Vec<OrderData> {
+        let Some(fair_value) = self.fair_value(history) else {
+            return vec![];
+        };
+
+        let inventory = self.inventory(account);
+        let sigma = self.volatility(history);
+        let reservation = fair_value - inventory.as_int as f64 * self.risk_aversion * sigma * sigma;
+        let half_spread = self.half_spread.as_int as f64 + self.volatility_factor * sigma;
+
+        let bid = (reservation - half_spread).floor() as i64;
+        let ask = ((reservation + half_spread).ceil() as i64).max(bid + 1);
+
+        let room_to_buy = self.limit.room(account, OrderSide::Bid);
+        let room_to_sell = self.limit.room(account, OrderSide::Ask);
+
+        let mut orders = Vec::new();
+        if bid > 0 && room_to_buy.is_positive() {
+            orders.push(OrderData {
+                side: OrderSide::Bid,
+                price: Some(Amount { as_int: bid }),
+                size: self.size.min(room_to_buy),
+            });
+        }
+        if ask > 0 && room_to_sell.is_positive() {
+            orders.push(OrderData {
+                side: OrderSide::Ask,
+                price: Some(Amount { as_int: ask }),
+                size: self.size.min(room_to_sell),
+            });
+        }
+        orders
+    }
+}
+
+impl<T> Agent for MarketMaker<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, _rng: SimRng) {}
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.limit.observe(account);
+        self.quotes(account, history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_market::{COMMODITY, MONEY, TestAgent, market, run, traded_at, traded_lots_at},
+        zero_intelligence::ZeroIntelligence,
+    };
+
+    type Maker = MarketMaker<()>;
+
+    fn maker() -> Maker {
+        Maker::new(Amount { as_int: 5 }, Quantity::new(2), Quantity::new(3))
+            .with_fair_value(Amount { as_int: 100 })
+            .with_risk_aversion(0.5)
+    }
+
+    fn holding(units: i64) -> Account {
+        Account {
+            commodity: Quantity::new(units),
+            ..Default::default()
+        }
+    }
+
+    fn prices(quotes: &[OrderData]) -> Vec<(OrderSide, i64, i64)> {
+        quotes
+            .iter()
+            .map(|o| (o.side, o.price.unwrap().as_int, o.size.as_int))
+            .collect()
+    }
+
+    #[test]
+    fn quotes_lean_against_inventory() {
+        let mut maker = maker();
+        maker.limit.observe(&holding(10));
+        let calm = traded_at(&[100, 102, 100, 102, 100], 10);
+
+        assert_eq!(
+            prices(&maker.quotes(&holding(10), &calm)),
+            vec![(OrderSide::Bid, 95, 2), (OrderSide::Ask, 105, 2)]
+        );
+
+        // long two units: both quotes move down, only one more unit may be bought
+        let long = prices(&maker.quotes(&holding(12), &calm));
+        assert!(long[0].1 < 95 && long[1].1 < 105);
+        assert_eq!(long[0].2, 1);
+
+        // at the cap only the side that unwinds is quoted
+        assert_eq!(maker.quotes(&holding(7), &calm)[0].side, OrderSide::Bid);
+        assert_eq!(maker.quotes(&holding(7), &calm).len(), 1);
+    }
+
+    #[test]
+    fn spread_widens_with_volatility() {
+        let mut maker = maker().with_volatility_factor(1.0);
+        maker.limit.observe(&holding(10));
+
+        let spread = |history: &History| {
+            let quotes = prices(&maker.quotes(&holding(10), history));
+            quotes[1].1 - quotes[0].1
+        };
+        assert!(
+            spread(&traded_at(&[100, 120, 90, 115, 85], 10))
+                > spread(&traded_at(&[100, 101, 100], 10))
+        );
+    }
+
+    #[test]
+    fn larger_fills_are_priced_per_unit() {
+        let mut maker = Maker::new(Amount { as_int: 5 }, Quantity::new(2), Quantity::new(3));
+        maker.limit.observe(&holding(10));
+        let lots = traded_lots_at(&[100, 120, 90, 115, 100], Quantity::new(2), 10);
+
+        assert_eq!(
+            prices(&maker.quotes(&holding(10), &lots)),
+            vec![(OrderSide::Bid, 95, 2), (OrderSide::Ask, 105, 2)]
+        );
+        assert_eq!(
+            maker.volatility(&lots),
+            maker.volatility(&traded_at(&[100, 120, 90, 115, 100], 10))
+        );
+    }
+
+    #[test]
+    fn inventory_stays_capped_among_noise_traders() {
+        let noise = |side| {
+            let values = vec![Amount { as_int: 0 }; 500];
+            let trader = ZeroIntelligence::unconstrained(
+                side,
+                values,
+                Amount { as_int: 80 },
+                Amount { as_int: 120 },
+            );
+            Box::new(trader) as TestAgent
+        };
+        let maker = Box::new(maker().with_volatility_factor(1.0)) as TestAgent;
+        let agents = vec![maker, noise(OrderSide::Bid), noise(OrderSide::Ask)];
+
+        let mut market = market(11);
+        let (ids, histories) = run(&mut market, agents, 100);
+        assert!(histories.iter().any(|h| !h.transactions.is_empty()));
+
+        let account = market.account(ids[0]).unwrap();
+        assert_ne!(account.money, Amount::units(MONEY));
+        let inventory = account.commodity.as_int - COMMODITY;
+        assert!(inventory.abs() <= 3, "{inventory}");
+    }
+}
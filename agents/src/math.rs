@@ -0,0 +1,101 @@
+// natural logarithm from plain arithmetic, so runs agree on every platform
+pub(crate) fn ln(mut x: f64) -> f64 {
+    assert!(x > 0.0 && x.is_finite(), "logarithm of {x}");
+    let mut halvings = 0.0;
+    while x > 2.0 {
+        x /= 2.0;
+        halvings += 1.0;
+    }
+    while x < 1.0 {
+        x *= 2.0;
+        halvings -= 1.0;
+    }
+
+    // ln x = 2 atanh((x - 1) / (x + 1)), the ratio is at most 1/3 here
+    let y = (x - 1.0) / (x + 1.0);
+    let mut term = y;
+    let mut sum = 0.0;
+    for n in 0..24 {
+        sum += term / (2 * n + 1) as f64;
+        term *= y * y;
+    }
+    halvings * std::f64::consts::LN_2 + 2.0 * sum
+}
+
+// exponential from plain arithmetic, for the same reason
+pub(crate) fn exp(x: f64) -> f64 {
+    if x < -745.0 {
+        return 0.0;
+    }
+    // beyond the largest finite result, also catches infinity
+    if x > 709.78 {
+        return f64::INFINITY;
+    }
+    if x.is_nan() {
+        return x;
+    }
+
+    // e^x = 2^k e^r with r in [0, ln 2)
+    let k = (x / std::f64::consts::LN_2).floor();
+    let r = x - k * std::f64::consts::LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..20 {
+        term *= r / n as f64;
+        sum += term;
+    }
+
+    let mut scale = 1.0;
+    let half = if k < 0.0 { 0.5 } else { 2.0 };
+    for _ in 0..k.abs() as i64 {
+        scale *= half;
+    }
+    sum * scale
+}
+
+// index of the largest value, the first one among equals
+pub(crate) fn argmax(values: &[f64]) -> usize {
+    let mut best = 0;
+    for (action, &value) in values.iter().enumerate() {
+        if value > values[best] {
+            best = action;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logarithm_matches_the_standard_one() {
+        for x in [1e-6, 0.3, 1.0, 2.0, 7.5, 1000.0, 1e9] {
+            assert!((ln(x) - x.ln()).abs() < 1e-12, "{x}");
+        }
+    }
+
+    #[test]
+    fn exponential_matches_the_standard_one() {
+        for x in [-50.0, -3.2, -0.5, 0.0, 0.7, 4.0, 20.0] {
+            assert!((exp(x) / x.exp() - 1.0).abs() < 1e-12, "{x}");
+        }
+        assert_eq!(exp(-1000.0), 0.0);
+        assert_eq!(exp(1e15), f64::INFINITY);
+        assert_eq!(exp(f64::INFINITY), f64::INFINITY);
+        assert_eq!(exp(f64::NEG_INFINITY), 0.0);
+        assert!(exp(f64::NAN).is_nan());
+        assert!((exp(709.7) / 709.7f64.exp() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "logarithm of inf")]
+    fn logarithm_of_infinity() {
+        ln(f64::INFINITY);
+    }
+
+    #[test]
+    fn argmax_takes_the_first_of_equals() {
+        assert_eq!(argmax(&[1.0, 3.0, 3.0, 2.0]), 1);
+    }
+}
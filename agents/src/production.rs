@@ -0,0 +1,506 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::{schedule::Schedule, zero_intelligence::max_surplus};
+
+/// Marginal cost of producing a unit, the first unit of a period is unit 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cost {
+    // every unit costs the same
+    Constant { cost: Amount },
+    // every unit costs `slope` more than the one before
+    Linear { base: Amount, slope: Amount },
+}
+
+impl Cost {
+    pub fn marginal(&self, unit: i64) -> Amount {
+        match *self {
+            Cost::Constant { cost } => cost,
+            Cost::Linear { base, slope } => base + slope * unit,
+        }
+    }
+
+    // cost of producing the first `units` units
+    pub fn total(&self, units: i64) -> Amount {
+        (0..units).map(|unit| self.marginal(unit)).sum()
+    }
+}
+
+/// Marginal utility of consuming a unit, the first unit of a period is unit 0.
+/// It diminishes with every unit and never goes below zero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Utility {
+    // quadratic utility, every unit is worth `slope` less than the one before
+    Linear { intercept: Amount, slope: Amount },
+    // logarithmic utility in steps, unit n is worth `scale / (n + 1)`
+    Hyperbolic { scale: Amount },
+}
+
+impl Utility {
+    pub fn marginal(&self, unit: i64) -> Amount {
+        let value = match *self {
+            Utility::Linear { intercept, slope } => intercept - slope * unit,
+            Utility::Hyperbolic { scale } => Amount {
+                as_int: scale.as_int / (unit + 1),
+            },
+        };
+        value.max(Amount::new())
+    }
+
+    // utility of consuming the first `units` units
+    pub fn total(&self, units: i64) -> Amount {
+        (0..units).map(|unit| self.marginal(unit)).sum()
+    }
+}
+
+/// Competitive equilibrium of a market: the units that trade and the range of prices
+/// that clear it, with the surplus they make.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Equilibrium {
+    pub quantity: Quantity,
+    pub low: Amount,
+    pub high: Amount,
+    pub surplus: Amount,
+}
+
+// `None` when no unit is worth more to a buyer than it costs a seller
+pub fn equilibrium(values: &[Amount], costs: &[Amount]) -> Option<Equilibrium> {
+    let mut values = values.to_vec();
+    let mut costs = costs.to_vec();
+    values.sort_by_key(|&value| std::cmp::Reverse(value));
+    costs.sort();
+
+    let quantity = values
+        .iter()
+        .zip(&costs)
+        .take_while(|(value, cost)| value > cost)
+        .count();
+    if quantity == 0 {
+        return None;
+    }
+
+    // the price has to keep the marginal units in and the next ones out
+    let mut low = costs[quantity - 1];
+    let mut high = values[quantity - 1];
+    if let Some(&value) = values.get(quantity) {
+        low = low.max(value);
+    }
+    if let Some(&cost) = costs.get(quantity) {
+        high = high.min(cost);
+    }
+
+    Some(Equilibrium {
+        quantity: Quantity::new(quantity as i64),
+        low,
+        high,
+        surplus: max_surplus(&values, &costs),
+    })
+}
+
+// one order per price along what is left of a schedule, `markup` away from the values
+fn curve_orders(schedule: &Schedule, account: &Account, markup: Amount) -> Vec<OrderData> {
+    let traded = schedule.traded(account).as_int.max(0) as usize;
+    let remaining = schedule.values.iter().skip(traded);
+
+    let mut orders: Vec<OrderData> = Vec::new();
+    for &value in remaining {
+        let price = match schedule.side {
+            OrderSide::Bid => value - markup,
+            OrderSide::Ask => value + markup,
+        };
+        if price.as_int <= 0 {
+            continue;
+        }
+
+        match orders.last_mut() {
+            Some(last) if last.price == Some(price) => last.size += Quantity::new(1),
+            _ => orders.push(OrderData {
+                side: schedule.side,
+                price: Some(price),
+                size: Quantity::new(1),
+            }),
+        }
+    }
+    orders
+}
+
+/// Mines up to `capacity` units a period and sells each at its marginal cost plus
+/// `markup`, so it asks along its supply curve. Units are made before it quotes and
+/// paid for when they are made, commodity it already holds is sold first and it
+/// never makes more than it can pay for.
+#[derive(Clone, Debug)]
+pub struct Producer<T> {
+    pub cost: Cost,
+    pub markup: Amount,
+    pub schedule: Schedule,
+
+    // commodity held when it last asked to produce and the units it asked for,
+    // restocked with what the market made once it sees the account again
+    producing: Option<(Quantity, Quantity)>,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> Producer<T> {
+    pub fn new(cost: Cost, capacity: Quantity) -> Self {
+        let values = (0..capacity.as_int)
+            .map(|unit| cost.marginal(unit))
+            .collect();
+        Self {
+            cost,
+            markup: Amount::new(),
+            schedule: Schedule::new(OrderSide::Ask, values),
+            producing: None,
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    // capacity is restored every `steps` steps
+    pub fn with_period(mut self, steps: u64) -> Self {
+        self.schedule = self.schedule.with_day_length(steps);
+        self
+    }
+
+    // production the market refused leaves the account as it was
+    fn restock(&mut self, account: &Account) {
+        if let Some((held, units)) = self.producing.take() {
+            let made = (account.commodity - held).clamp(Quantity::ZERO, units);
+            self.schedule.restock(made);
+        }
+    }
+
+    pub fn with_markup(mut self, markup: Amount) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    // units it would produce at `price`
+    pub fn supply(&self, price: Amount) -> Quantity {
+        let units = self.schedule.values.iter().filter(|&&cost| cost <= price);
+        Quantity::new(units.count() as i64)
+    }
+}
+
+impl<T> Agent for Producer<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, _rng: SimRng) {}
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.restock(account);
+        self.schedule.observe(account, history.step);
+
+        // never more than it holds, see `production`
+        let mut stock = account.free_commodity();
+        let mut orders = curve_orders(&self.schedule, account, self.markup);
+        for order in &mut orders {
+            order.size = order.size.min(stock);
+            stock -= order.size;
+        }
+        orders.retain(|order| order.size.is_positive());
+        orders
+    }
+
+    fn production(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Option<(Quantity, Amount)> {
+        self.restock(account);
+        self.schedule.observe(account, history.step);
+
+        // what is left of the period less what it holds, in the order it is sold
+        let traded = self.schedule.traded(account).as_int.max(0);
+        let held = account.free_commodity().as_int.max(0);
+        let next = self.schedule.values.iter().skip((traded + held) as usize);
+
+        let budget = account.purchasing_power();
+        let (mut units, mut cost) = (Quantity::ZERO, Amount::new());
+        for &marginal in next {
+            if cost + marginal > budget {
+                break;
+            }
+            units += Quantity::new(1);
+            cost += marginal;
+        }
+
+        if !units.is_positive() {
+            return None;
+        }
+        self.producing = Some((account.commodity, units));
+        Some((units, cost))
+    }
+}
+
+/// Consumes up to `capacity` units a period, refineries and colonies alike, and bids
+/// the marginal utility of each less `markup`, so it bids along its demand curve.
+/// Units worth nothing to it are not bid for.
+#[derive(Clone, Debug)]
+pub struct Consumer<T> {
+    pub utility: Utility,
+    pub markup: Amount,
+    pub schedule: Schedule,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> Consumer<T> {
+    pub fn new(utility: Utility, capacity: Quantity) -> Self {
+        let values = (0..capacity.as_int)
+            .map(|unit| utility.marginal(unit))
+            .take_while(|value| value.as_int > 0)
+            .collect();
+        Self {
+            utility,
+            markup: Amount::new(),
+            schedule: Schedule::new(OrderSide::Bid, values),
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    // appetite is restored every `steps` steps
+    pub fn with_period(mut self, steps: u64) -> Self {
+        self.schedule = self.schedule.with_day_length(steps);
+        self
+    }
+
+    pub fn with_markup(mut self, markup: Amount) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    // units it would consume at `price`
+    pub fn demand(&self, price: Amount) -> Quantity {
+        let units = self.schedule.values.iter().filter(|&&value| value >= price);
+        Quantity::new(units.count() as i64)
+    }
+}
+
+impl<T> Agent for Consumer<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, _rng: SimRng) {}
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.schedule.observe(account, history.step);
+        curve_orders(&self.schedule, account, self.markup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_market::{
+        COMMODITY, MONEY, TestAgent, cents, endowment, market, run_with, schedules,
+    };
+    use market::market::MarketInfo;
+
+    fn cost(base: i64, slope: i64) -> Cost {
+        Cost::Linear {
+            base: Amount { as_int: base },
+            slope: Amount { as_int: slope },
+        }
+    }
+
+    fn utility(intercept: i64, slope: i64) -> Utility {
+        Utility::Linear {
+            intercept: Amount { as_int: intercept },
+            slope: Amount { as_int: slope },
+        }
+    }
+
+    #[test]
+    fn curves_give_prices_and_sizes() {
+        assert_eq!(cost(50, 10).total(3), Amount { as_int: 180 });
+        assert_eq!(utility(100, 40).marginal(3), Amount::new());
+        let log = Utility::Hyperbolic {
+            scale: Amount { as_int: 120 },
+        };
+        assert_eq!(
+            log.total(4),
+            Amount {
+                as_int: 120 + 60 + 40 + 30
+            }
+        );
+
+        let producer = Producer::<()>::new(cost(50, 10), Quantity::new(5));
+        assert_eq!(producer.supply(Amount { as_int: 75 }), Quantity::new(3));
+        let consumer = Consumer::<()>::new(utility(100, 40), Quantity::new(5));
+        assert_eq!(consumer.schedule.values, cents(&[100, 60, 20]));
+        assert_eq!(consumer.demand(Amount { as_int: 50 }), Quantity::new(2));
+
+        // equal prices are bid together, the stock caps what is asked
+        let info = MarketInfo {
+            name: "production".to_owned(),
+            commodity: (),
+        };
+        let history = History::default();
+        let mut flat = Producer::<()>::new(
+            Cost::Constant {
+                cost: Amount { as_int: 70 },
+            },
+            Quantity::new(4),
+        )
+        .with_markup(Amount { as_int: 5 });
+        let account = Account {
+            commodity: Quantity::new(3),
+            ..Default::default()
+        };
+        let asks = flat.produce_orders(&account, &info, &history);
+        assert_eq!(asks.len(), 1);
+        assert_eq!(asks[0].price, Some(Amount { as_int: 75 }));
+        assert_eq!(asks[0].size, Quantity::new(3));
+
+        // it makes the one unit it doesn't hold, given the money to pay for it
+        let made = flat.production(&account, &info, &history);
+        assert_eq!(made, None);
+        let account = Account {
+            money: Amount { as_int: 100 },
+            ..account
+        };
+        let made = flat.production(&account, &info, &history);
+        assert_eq!(made, Some((Quantity::new(1), Amount { as_int: 70 })));
+    }
+
+    #[test]
+    fn only_what_is_made_is_restocked() {
+        let info = MarketInfo {
+            name: "production".to_owned(),
+            commodity: (),
+        };
+        let history = History::default();
+        let account = Account {
+            money: Amount { as_int: 100 },
+            ..Default::default()
+        };
+
+        // the third unit would make the cost negative, so the market refuses it
+        let mut producer = Producer::<()>::new(cost(10, -20), Quantity::new(3));
+        let made = producer.production(&account, &info, &history);
+        assert_eq!(made, Some((Quantity::new(3), Amount { as_int: -30 })));
+        let mut market = market(6);
+        let id = market.register_with_acc(account);
+        assert!(!market.produce(id, Quantity::new(3), Amount { as_int: -30 }));
+
+        let account = market.account(id).unwrap();
+        assert!(
+            producer
+                .produce_orders(&account, &info, &history)
+                .is_empty()
+        );
+        assert_eq!(producer.schedule.traded(&account), Quantity::ZERO);
+
+        // made units are stock, not sales
+        let mut producer = Producer::<()>::new(cost(50, 0), Quantity::new(3));
+        let made = producer.production(&account, &info, &history).unwrap();
+        assert!(market.produce(id, made.0, made.1));
+
+        let account = market.account(id).unwrap();
+        let asks = producer.produce_orders(&account, &info, &history);
+        assert_eq!(asks[0].size, Quantity::new(2));
+        assert_eq!(producer.schedule.traded(&account), Quantity::ZERO);
+    }
+
+    #[test]
+    fn equilibrium_of_the_test_schedules() {
+        let (buyers, sellers) = schedules();
+        let equilibrium = equilibrium(&buyers.concat(), &sellers.concat()).unwrap();
+        assert_eq!(equilibrium.quantity, Quantity::new(9));
+        assert_eq!(equilibrium.low, Amount { as_int: 100 });
+        assert_eq!(equilibrium.high, Amount { as_int: 110 });
+        assert_eq!(equilibrium.surplus, Amount { as_int: 810 });
+
+        assert_eq!(super::equilibrium(&cents(&[50]), &cents(&[60])), None);
+    }
+
+    #[test]
+    fn truthful_curves_reach_the_equilibrium() {
+        let producers: Vec<Producer<()>> = (0..4)
+            .map(|n| Producer::new(cost(20 + 10 * n, 40), Quantity::new(4)))
+            .collect();
+        let consumers: Vec<Consumer<()>> = (0..4)
+            .map(|n| Consumer::new(utility(190 - 10 * n, 40), Quantity::new(4)))
+            .collect();
+
+        let costs: Vec<Amount> = producers
+            .iter()
+            .flat_map(|p| p.schedule.values.clone())
+            .collect();
+        let values: Vec<Amount> = consumers
+            .iter()
+            .flat_map(|c| c.schedule.values.clone())
+            .collect();
+        let expected = equilibrium(&values, &costs).unwrap();
+
+        // producers start with nothing to sell and make what they sell
+        let producing = Account {
+            money: Amount::units(MONEY),
+            ..Default::default()
+        };
+        let agents = (producers.iter().cloned())
+            .map(|p| (Box::new(p) as TestAgent, producing))
+            .chain(
+                consumers
+                    .iter()
+                    .cloned()
+                    .map(|c| (Box::new(c) as TestAgent, endowment())),
+            )
+            .collect();
+        let mut market = market(5);
+        let (ids, histories) = run_with(&mut market, agents, 10);
+
+        let volume: i64 = histories
+            .iter()
+            .flat_map(|h| &h.transactions)
+            .map(|t| t.size.as_int)
+            .sum();
+        assert_eq!(volume, expected.quantity.as_int);
+
+        // welfare is the utility consumed less the cost produced, producers pay for
+        // every unit they make and the ones left unsold are wasted
+        let mut waste = Amount::new();
+        let welfare: Amount = ids
+            .iter()
+            .enumerate()
+            .map(|(n, id)| {
+                let account = market.account(*id).unwrap();
+                let money = account.money - Amount::units(MONEY);
+                match n {
+                    0..4 => {
+                        let cost = producers[n].cost;
+                        let sold = 4 - account.commodity.as_int;
+                        waste += cost.total(4) - cost.total(sold);
+                        money
+                    }
+                    _ => {
+                        let units = account.commodity.as_int - COMMODITY;
+                        money + consumers[n - 4].utility.total(units)
+                    }
+                }
+            })
+            .sum();
+        assert!(waste.as_int > 0);
+        assert_eq!(
+            welfare + market.market_account().money,
+            expected.surplus - waste
+        );
+    }
+}
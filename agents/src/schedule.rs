@@ -0,0 +1,81 @@
+use market::{account::Account, amount::Amount, orders::flat::OrderSide, quantity::Quantity};
+
+/// Units a trader wants to buy or sell, each with its own redemption value or cost,
+/// traded in order. Fills are counted from the commodity held when the schedule
+/// (re)started, so it works with any settlement the market uses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    pub side: OrderSide,
+    pub values: Vec<Amount>,
+    // steps in a trading day, the schedule is refilled at the start of every day
+    pub day_length: Option<u64>,
+
+    start: Option<Quantity>,
+}
+
+impl Schedule {
+    pub fn new(side: OrderSide, values: Vec<Amount>) -> Self {
+        Self {
+            side,
+            values,
+            day_length: None,
+            start: None,
+        }
+    }
+
+    pub fn with_day_length(mut self, steps: u64) -> Self {
+        self.day_length = Some(steps);
+        self
+    }
+
+    // called with the history step before quoting, starts a new day when one is due
+    pub fn observe(&mut self, account: &Account, step: u64) {
+        let new_day = self
+            .day_length
+            .is_some_and(|steps| step.is_multiple_of(steps));
+
+        if self.start.is_none() || new_day {
+            self.start = Some(account.commodity);
+        }
+    }
+
+    // units that came in other than by trading, they don't count as traded
+    pub fn restock(&mut self, units: Quantity) {
+        self.start = self.start.map(|start| start + units);
+    }
+
+    pub fn traded(&self, account: &Account) -> Quantity {
+        let Some(start) = self.start else {
+            return Quantity::ZERO;
+        };
+
+        match self.side {
+            OrderSide::Bid => account.commodity - start,
+            OrderSide::Ask => start - account.commodity,
+        }
+    }
+
+    // value of the next unit to trade, `None` once the schedule is done
+    pub fn next_value(&self, account: &Account) -> Option<Amount> {
+        let traded = self.traded(account).as_int.max(0) as usize;
+        self.values.get(traded).copied()
+    }
+
+    // value of the worst of the next `units` units, the lowest redemption value
+    // of a buyer or the highest cost of a seller, `None` once the schedule is done
+    pub fn worst_value(&self, account: &Account, units: Quantity) -> Option<Amount> {
+        let traded = self.traded(account).as_int.max(0) as usize;
+        let next = self.values.iter().skip(traded);
+        let next = next.take(units.as_int.max(0) as usize).copied();
+
+        match self.side {
+            OrderSide::Bid => next.min(),
+            OrderSide::Ask => next.max(),
+        }
+    }
+
+    pub fn remaining(&self, account: &Account) -> Quantity {
+        let traded = self.traded(account).as_int.max(0);
+        Quantity::new((self.values.len() as i64 - traded).max(0))
+    }
+}
@@ -0,0 +1,234 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::schedule::Schedule;
+
+/// Gode–Sunder zero-intelligence trader. It shouts one order per step at a uniformly
+/// random price in `min_price..=max_price`, for the next units of its schedule.
+/// A constrained (ZI-C) trader cuts the range at the worst value among those units,
+/// so it never buys above a valuation or sells below a cost. An unconstrained (ZI-U)
+/// one ignores its values when pricing.
+pub struct ZeroIntelligence<T> {
+    pub schedule: Schedule,
+    pub min_price: Amount,
+    pub max_price: Amount,
+    pub size: Quantity,
+    pub constrained: bool,
+
+    rng: SimRng,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> ZeroIntelligence<T> {
+    pub fn unconstrained(
+        side: OrderSide,
+        values: Vec<Amount>,
+        min_price: Amount,
+        max_price: Amount,
+    ) -> Self {
+        Self {
+            schedule: Schedule::new(side, values),
+            min_price,
+            max_price,
+            size: Quantity::new(1),
+            constrained: false,
+            rng: SimRng::seed_from_u64(0),
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn constrained(
+        side: OrderSide,
+        values: Vec<Amount>,
+        min_price: Amount,
+        max_price: Amount,
+    ) -> Self {
+        Self {
+            constrained: true,
+            ..Self::unconstrained(side, values, min_price, max_price)
+        }
+    }
+
+    pub fn with_size(mut self, size: Quantity) -> Self {
+        self.size = size;
+        self
+    }
+
+    fn quote(&mut self, value: Amount) -> Option<Amount> {
+        let (min, max) = match (self.constrained, self.schedule.side) {
+            (false, _) => (self.min_price, self.max_price),
+            (true, OrderSide::Bid) => (self.min_price, self.max_price.min(value)),
+            (true, OrderSide::Ask) => (self.min_price.max(value), self.max_price),
+        };
+
+        let min = min.max(Amount { as_int: 1 });
+        if min > max {
+            return None;
+        }
+
+        Some(Amount {
+            as_int: self.rng.range(min.as_int..=max.as_int),
+        })
+    }
+}
+
+impl<T> Agent for ZeroIntelligence<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, rng: SimRng) {
+        self.rng = rng;
+    }
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.schedule.observe(account, history.step);
+
+        let size = self.size.min(self.schedule.remaining(account));
+        let Some(value) = self.schedule.worst_value(account, size) else {
+            return vec![];
+        };
+        let Some(price) = self.quote(value) else {
+            return vec![];
+        };
+
+        vec![OrderData {
+            side: self.schedule.side,
+            price: Some(price),
+            size,
+        }]
+    }
+}
+
+/// Largest surplus the schedules can produce: the units traded where the demand
+/// curve lies above the supply curve, the area between them.
+pub fn max_surplus(values: &[Amount], costs: &[Amount]) -> Amount {
+    let mut values = values.to_vec();
+    let mut costs = costs.to_vec();
+    values.sort_by_key(|&value| std::cmp::Reverse(value));
+    costs.sort();
+
+    values
+        .into_iter()
+        .zip(costs)
+        .take_while(|(value, cost)| value > cost)
+        .map(|(value, cost)| value - cost)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_market::{COMMODITY, MONEY, TestAgent, cents, market, run, schedules};
+
+    type Trader = ZeroIntelligence<()>;
+
+    // allocative efficiency of a session and the surplus each trader made
+    fn session(make: fn(OrderSide, Vec<Amount>) -> Trader, seed: u64) -> (f64, Vec<i64>) {
+        let (buyers, sellers) = schedules();
+        let traders: Vec<_> = (buyers.iter().map(|v| (OrderSide::Bid, v.clone())))
+            .chain(sellers.iter().map(|v| (OrderSide::Ask, v.clone())))
+            .collect();
+
+        let mut market = market(seed);
+        let agents = traders
+            .iter()
+            .map(|(side, values)| Box::new(make(*side, values.clone())) as TestAgent)
+            .collect();
+        let (ids, _) = run(&mut market, agents, 200);
+
+        let surplus: Vec<i64> = (ids.iter().zip(&traders))
+            .map(|(id, (side, values))| {
+                let account = market.account(*id).unwrap();
+                let traded = match side {
+                    OrderSide::Bid => account.commodity.as_int - COMMODITY,
+                    OrderSide::Ask => COMMODITY - account.commodity.as_int,
+                };
+                let value: i64 = values[..traded as usize].iter().map(|v| v.as_int).sum();
+                let paid = (Amount::units(MONEY) - account.money).as_int;
+
+                match side {
+                    OrderSide::Bid => value - paid,
+                    OrderSide::Ask => -paid - value,
+                }
+            })
+            .collect();
+
+        // the spread between matched quotes goes to the market, allocation doesn't care
+        let realised = surplus.iter().sum::<i64>() + market.market_account().money.as_int;
+        let values: Vec<Amount> = buyers.concat();
+        let costs: Vec<Amount> = sellers.concat();
+        let efficiency = realised as f64 / max_surplus(&values, &costs).as_int as f64;
+
+        (efficiency, surplus)
+    }
+
+    fn zi_c(side: OrderSide, values: Vec<Amount>) -> Trader {
+        Trader::constrained(side, values, Amount { as_int: 1 }, Amount { as_int: 200 })
+    }
+
+    fn zi_u(side: OrderSide, values: Vec<Amount>) -> Trader {
+        Trader::unconstrained(side, values, Amount { as_int: 1 }, Amount { as_int: 200 })
+    }
+
+    #[test]
+    fn max_surplus_is_the_area_between_the_curves() {
+        let (buyers, sellers) = schedules();
+        assert_eq!(
+            max_surplus(&buyers.concat(), &sellers.concat()),
+            Amount { as_int: 810 }
+        );
+        assert_eq!(max_surplus(&cents(&[10]), &cents(&[10])), Amount::new());
+    }
+
+    #[test]
+    fn constrained_traders_are_efficient_and_never_lose() {
+        for seed in 0..5 {
+            let (efficiency, surplus) = session(zi_c, seed);
+            assert!(efficiency > 0.9, "seed {seed}: efficiency {efficiency}");
+            assert!(surplus.iter().all(|&s| s >= 0), "seed {seed}: {surplus:?}");
+
+            let (unconstrained, _) = session(zi_u, seed);
+            assert!(unconstrained < efficiency, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn constrained_orders_respect_every_unit() {
+        let info = market::market::MarketInfo {
+            name: "zi".to_owned(),
+            commodity: (),
+        };
+        let account = Account::default();
+        let history = History::default();
+
+        let mut buyer = zi_c(OrderSide::Bid, cents(&[150, 40, 120])).with_size(Quantity::new(2));
+        let mut seller = zi_c(OrderSide::Ask, cents(&[50, 160])).with_size(Quantity::new(3));
+        for _ in 0..50 {
+            let bid = buyer.produce_orders(&account, &info, &history)[0];
+            assert_eq!(bid.size, Quantity::new(2));
+            assert!(bid.price.unwrap() <= Amount { as_int: 40 });
+
+            let ask = seller.produce_orders(&account, &info, &history)[0];
+            assert_eq!(ask.size, Quantity::new(2));
+            assert!(ask.price.unwrap() >= Amount { as_int: 160 });
+        }
+    }
+
+    #[test]
+    fn sessions_are_reproducible() {
+        assert_eq!(session(zi_u, 7), session(zi_u, 7));
+        assert_ne!(session(zi_u, 7).1, session(zi_u, 8).1);
+    }
+}
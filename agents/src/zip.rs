@@ -0,0 +1,340 @@
+use market::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::History,
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+    rng::SimRng,
+};
+
+use crate::schedule::Schedule;
+
+// what a ZIP trader learns from: a price and whether it was traded at
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Shout {
+    // for a trade the side of the order that took the resting one, if known
+    pub side: Option<OrderSide>,
+    pub price: f64,
+    pub accepted: bool,
+}
+
+impl Shout {
+    // trades of the last step first, then the quotes left unfilled
+    pub fn from_history(history: &History) -> Vec<Shout> {
+        let trades = history.transactions.iter().map(|trns| Shout {
+            side: trns.maker.map(|maker| match maker {
+                OrderSide::Bid => OrderSide::Ask,
+                OrderSide::Ask => OrderSide::Bid,
+            }),
+            price: (trns.bid_loss + trns.ask_gain).as_int as f64 / 2.0 / trns.size.as_int as f64,
+            accepted: true,
+        });
+
+        let quotes = history.unfulfilled_orders.iter().flat_map(|(_, order)| {
+            order.price.map(|price| Shout {
+                side: Some(order.side),
+                price: price.as_int as f64,
+                accepted: false,
+            })
+        });
+
+        trades.chain(quotes).collect()
+    }
+}
+
+/// Cliff's Zero-Intelligence-Plus trader. It quotes its unit value times `1 + margin`
+/// and moves the quote towards a target just beyond every observed shout with the
+/// Widrow-Hoff rule, smoothed by momentum. Buyers keep a margin in `-1..=0`, sellers
+/// a non-negative one, so neither trades at a loss.
+pub struct Zip<T> {
+    pub schedule: Schedule,
+    pub size: Quantity,
+
+    // beta, how far a quote moves towards its target per shout
+    pub learning_rate: f64,
+    // gamma, the share of the previous change carried into the next one
+    pub momentum: f64,
+    // largest relative and absolute (in cents) perturbation of a target
+    pub relative_perturbation: f64,
+    pub absolute_perturbation: f64,
+
+    margin: Option<f64>,
+    last_change: f64,
+    rng: SimRng,
+
+    pub _ph: std::marker::PhantomData<T>,
+}
+
+impl<T> Zip<T> {
+    pub fn new(side: OrderSide, values: Vec<Amount>) -> Self {
+        Self {
+            schedule: Schedule::new(side, values),
+            size: Quantity::new(1),
+            learning_rate: 0.3,
+            momentum: 0.05,
+            relative_perturbation: 0.05,
+            absolute_perturbation: 5.0,
+            margin: None,
+            last_change: 0.0,
+            rng: SimRng::seed_from_u64(0),
+            _ph: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        assert!((0.0..=1.0).contains(&learning_rate));
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        assert!((0.0..=1.0).contains(&momentum));
+        self.momentum = momentum;
+        self
+    }
+
+    // starting margin, drawn from the stream at setup when not given
+    pub fn with_margin(mut self, margin: f64) -> Self {
+        self.margin = Some(self.clamp(margin));
+        self
+    }
+
+    pub fn with_size(mut self, size: Quantity) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn margin(&self) -> Option<f64> {
+        self.margin
+    }
+
+    fn clamp(&self, margin: f64) -> f64 {
+        match self.schedule.side {
+            OrderSide::Bid => margin.clamp(-1.0, 0.0),
+            OrderSide::Ask => margin.max(0.0),
+        }
+    }
+
+    fn price(&self, value: Amount) -> f64 {
+        value.as_int as f64 * (1.0 + self.margin.unwrap_or_default())
+    }
+
+    // a target just above (`up`) or below the shout
+    fn target(&mut self, shout: f64, up: bool) -> f64 {
+        let relative = self.rng.next_f64() * self.relative_perturbation;
+        let absolute = self.rng.next_f64() * self.absolute_perturbation;
+
+        if up {
+            shout * (1.0 + relative) + absolute
+        } else {
+            shout * (1.0 - relative) - absolute
+        }
+    }
+
+    fn learn(&mut self, value: Amount, target: f64) {
+        let price = self.price(value);
+        let change = self.learning_rate * (target - price);
+        self.last_change = self.momentum * self.last_change + (1.0 - self.momentum) * change;
+
+        let margin = (price + self.last_change) / value.as_int as f64 - 1.0;
+        self.margin = Some(self.clamp(margin));
+    }
+
+    // the update rules of the original paper, for a trader that still has units
+    fn observe(&mut self, value: Amount, shout: Shout) {
+        let price = self.price(value);
+        let q = shout.price;
+
+        let (raise, lower) = match self.schedule.side {
+            OrderSide::Ask => (
+                shout.accepted && price <= q,
+                price >= q
+                    && match shout.side {
+                        None => true,
+                        Some(side) => shout.accepted == (side == OrderSide::Bid),
+                    },
+            ),
+            OrderSide::Bid => (
+                shout.accepted && price >= q,
+                price <= q
+                    && match shout.side {
+                        None => true,
+                        Some(side) => shout.accepted == (side == OrderSide::Ask),
+                    },
+            ),
+        };
+
+        // a seller raises its margin by asking more, a buyer by bidding less
+        let up = match self.schedule.side {
+            OrderSide::Ask => raise,
+            OrderSide::Bid => lower && !raise,
+        };
+        if raise || lower {
+            let target = self.target(q, up);
+            self.learn(value, target);
+        }
+    }
+}
+
+impl<T> Agent for Zip<T> {
+    type CommodityType = T;
+
+    fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, rng: SimRng) {
+        self.rng = rng;
+        if self.margin.is_none() {
+            let margin = 0.05 + 0.3 * self.rng.next_f64();
+            self.margin = Some(match self.schedule.side {
+                OrderSide::Bid => -margin,
+                OrderSide::Ask => margin,
+            });
+        }
+    }
+
+    fn produce_orders(
+        &mut self,
+        account: &Account,
+        _info: &Self::MarketInfoType,
+        history: &History,
+    ) -> Vec<OrderData> {
+        self.schedule.observe(account, history.step);
+        let Some(value) = self.schedule.next_value(account) else {
+            return vec![];
+        };
+
+        for shout in Shout::from_history(history) {
+            self.observe(value, shout);
+        }
+
+        let price = self.price(value).round() as i64;
+        if price <= 0 {
+            return vec![];
+        }
+
+        vec![OrderData {
+            side: self.schedule.side,
+            price: Some(Amount { as_int: price }),
+            size: self.size.min(self.schedule.remaining(account)),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_market::{TestAgent, market, run, schedules};
+    use market::{market::MarketInfo, order_book::Transaction, orders::flat::Order};
+
+    type Trader = Zip<()>;
+
+    fn info() -> MarketInfo<()> {
+        MarketInfo {
+            name: "zip".to_owned(),
+            commodity: (),
+        }
+    }
+
+    fn quote(trader: &mut Trader, history: &History) -> i64 {
+        let orders = trader.produce_orders(&Account::default(), &info(), history);
+        orders[0].price.unwrap().as_int
+    }
+
+    #[test]
+    fn margins_follow_the_shouts() {
+        let mut seller = Trader::new(OrderSide::Ask, vec![Amount { as_int: 100 }]).with_margin(0.1);
+        let mut buyer = Trader::new(OrderSide::Bid, vec![Amount { as_int: 200 }]).with_margin(-0.1);
+        assert_eq!(quote(&mut seller, &History::default()), 110);
+        assert_eq!(quote(&mut buyer, &History::default()), 180);
+
+        // a trade above both quotes: the seller asks more, the buyer bids more
+        let trade = History {
+            transactions: vec![Transaction {
+                size: Quantity::new(2),
+                bid_loss: Amount { as_int: 380 },
+                ask_gain: Amount { as_int: 380 },
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(quote(&mut seller, &trade) > 110);
+        assert!(quote(&mut buyer, &trade) > 180);
+
+        // an ask nobody took below the seller's quote pulls it down
+        let before = quote(&mut seller, &History::default());
+        let unfilled = History {
+            unfulfilled_orders: vec![(
+                AgentId::new(9),
+                Order {
+                    timestamp: 0,
+                    id: 0,
+                    side: OrderSide::Ask,
+                    price: Some(Amount { as_int: 105 }),
+                    size: Quantity::new(1),
+                },
+            )],
+            ..Default::default()
+        };
+        assert!(quote(&mut seller, &unfilled) < before);
+        assert!(seller.margin().unwrap() >= 0.0);
+    }
+
+    #[test]
+    fn trades_carry_the_aggressor_side() {
+        let trade = |maker| History {
+            transactions: vec![Transaction {
+                size: Quantity::new(1),
+                bid_loss: Amount { as_int: 100 },
+                ask_gain: Amount { as_int: 100 },
+                maker,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let shouts = Shout::from_history(&trade(Some(OrderSide::Bid)));
+        assert_eq!(shouts[0].side, Some(OrderSide::Ask));
+        assert_eq!(Shout::from_history(&trade(None))[0].side, None);
+
+        // a seller above the trade only gives in when a buyer took an ask
+        let seller = || Trader::new(OrderSide::Ask, vec![Amount { as_int: 100 }]).with_margin(0.1);
+        let mut hit = seller();
+        assert_eq!(quote(&mut hit, &trade(Some(OrderSide::Bid))), 110);
+        let mut lifted = seller();
+        assert!(quote(&mut lifted, &trade(Some(OrderSide::Ask))) < 110);
+    }
+
+    #[test]
+    fn prices_converge_to_the_equilibrium() {
+        let (buyers, sellers) = schedules();
+        let day = 10;
+        let agents = (buyers.into_iter().map(|v| (OrderSide::Bid, v)))
+            .chain(sellers.into_iter().map(|v| (OrderSide::Ask, v)))
+            .map(|(side, values)| {
+                let mut trader = Trader::new(side, values)
+                    .with_learning_rate(0.3)
+                    .with_momentum(0.1);
+                trader.schedule = trader.schedule.with_day_length(day);
+                Box::new(trader) as TestAgent
+            })
+            .collect();
+
+        let (_, histories) = run(&mut market(5), agents, 30 * day);
+
+        let day_prices = |n: usize| {
+            let trades: Vec<f64> = histories[n * day as usize..(n + 1) * day as usize]
+                .iter()
+                .flat_map(Shout::from_history)
+                .filter(|shout| shout.accepted)
+                .map(|shout| shout.price)
+                .collect();
+            // root mean square distance of the prices from the 1.05 equilibrium
+            let deviation = trades.iter().map(|p| (p - 105.0).powi(2)).sum::<f64>();
+            (trades.len(), (deviation / trades.len() as f64).sqrt())
+        };
+
+        let (_, first) = day_prices(0);
+        let (volume, last) = day_prices(29);
+        assert!(last < first);
+        assert!(last < 5.0, "{last}");
+        assert!((7..=10).contains(&volume), "{volume}");
+    }
+}
@@ -66,9 +66,9 @@ impl eframe::App for AsteroidApp {
             let max_y = self
                 .data
                 .iter()
-                .map(|x| x.as_int)
-                .reduce(i64::max)
-                .unwrap_or(0) as f32;
+                .map(|x| x.to_f64())
+                .reduce(f64::max)
+                .unwrap_or(0.0);
             ui.heading("Main panel");
             let plot = egui_plot::Plot::new("sin")
                 .legend(egui_plot::Legend::default())
@@ -113,7 +113,7 @@ impl eframe::App for AsteroidApp {
                         self.data
                             .iter()
                             .enumerate()
-                            .map(|(i, v)| [i as f64, v.as_int as f64])
+                            .map(|(i, v)| [i as f64, v.to_f64()])
                             .collect(),
                     ))
                     .color(egui::Color32::from_rgb(200, 100, 100))
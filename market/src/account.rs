@@ -1,57 +1,163 @@
 use crate::{
     amount::Amount,
+    fees::FeeSchedule,
+    lending::Locate,
     orders::{
         flat::{Order, OrderData, OrderSide},
         limit::LimitOrder,
         market::MarketOrder,
     },
+    quantity::Quantity,
 };
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Account {
-    pub commodity: i64,
+    pub commodity: Quantity,
     pub money: Amount,
     pub reserved_money: Amount,
-    pub reserved_commodity: i64,
+    pub reserved_commodity: Quantity,
     pub dept: Amount,
+    pub credit_limit: Amount,
+    pub borrowed_commodity: Quantity,
+    // money posted against borrowed commodity
+    pub collateral: Amount,
+    // owed to trades that haven't settled yet
+    pub locked_money: Amount,
+    pub locked_commodity: Quantity,
 }
 
 impl Account {
     pub fn starting_account() -> Self {
         Account {
-            commodity: 10,
+            commodity: Quantity::new(10),
             money: Amount { as_int: 10 },
             ..Default::default()
         }
     }
 
-    pub fn reservable(&self, orders: &[&OrderData]) -> bool {
-        let (money, comm) = orders.iter().fold((0, 0), |(l, r), &data| match data.side {
-            OrderSide::Bid => (l + data.price.unwrap_or_default().as_int * data.size, r),
-            OrderSide::Ask => (l, r + data.size),
-        });
+    // net worth with commodity, held and owed, valued at the reference price,
+    // saturating where it doesn't fit
+    pub fn equity(&self, reference_price: Amount) -> Amount {
+        let held = reference_price.wide_mul(self.commodity.as_int)
+            - reference_price.wide_mul(self.borrowed_commodity.as_int);
+        let money =
+            self.money.as_int as i128 + self.collateral.as_int as i128 - self.dept.as_int as i128;
+        Amount::saturating_from_wide(money + held)
+    }
+
+    // money that can be spent, including the unused part of the credit line
+    pub fn purchasing_power(&self) -> Amount {
+        let unused_credit = Amount {
+            as_int: (self.credit_limit.as_int - self.dept.as_int).max(0),
+        };
+
+        self.money - self.locked_money + unused_credit
+    }
+
+    // money that is neither reserved for orders nor owed to unsettled trades
+    pub fn free_money(&self) -> Amount {
+        self.money - self.reserved_money - self.locked_money
+    }
+
+    // commodity that is neither reserved for orders nor owed to unsettled trades
+    pub fn free_commodity(&self) -> Quantity {
+        self.commodity - self.reserved_commodity - self.locked_commodity
+    }
+
+    // what has to be drawn from the lender to cover negative money, never past the
+    // credit limit, negative when positive money can repay debt
+    pub fn credit_draw(&self) -> Amount {
+        if self.money.as_int < 0 {
+            let unused_credit = (self.credit_limit - self.dept).max(Amount::new());
+            (-self.money).min(unused_credit)
+        } else {
+            -self.money.min(self.dept)
+        }
+    }
+
+    pub fn reservable(&self, orders: &[&OrderData], fees: &FeeSchedule) -> bool {
+        // overflowing totals can't be reserved
+        let totals = orders
+            .iter()
+            .try_fold((Amount::new(), Quantity::ZERO), |(l, r), &data| {
+                let charges = fees.reserve(&Order {
+                    timestamp: 0,
+                    id: 0,
+                    side: data.side,
+                    price: data.price,
+                    size: data.size,
+                });
 
-        self.commodity >= self.reserved_commodity + comm
-            && self.money >= self.reserved_money + Amount { as_int: money }
+                match data.side {
+                    OrderSide::Bid => {
+                        let value = data.price.unwrap_or_default().checked_value(data.size)?;
+                        Some((l.checked_add(value)?.checked_add(charges)?, r))
+                    }
+                    OrderSide::Ask => Some((l.checked_add(charges)?, r.checked_add(data.size)?)),
+                }
+            });
+
+        let Some((money, comm)) = totals else {
+            return false;
+        };
+
+        self.free_commodity() >= comm
+            && self
+                .reserved_money
+                .checked_add(money)
+                .is_some_and(|needed| self.purchasing_power() >= needed)
     }
 
-    pub fn reserve_order(&mut self, order: Order) -> bool {
-        if let Result::Ok(limit_order) = order.try_into() {
-            self.reserve_limit_order(&limit_order)
+    pub fn reserve_order(&mut self, order: Order, fees: &FeeSchedule) -> bool {
+        self.reserve_order_located(order, fees, Locate::default())
+    }
+
+    // located commodity can be sold on top of the holdings, its collateral is set aside
+    pub fn reserve_order_located(
+        &mut self,
+        order: Order,
+        fees: &FeeSchedule,
+        locate: Locate,
+    ) -> bool {
+        let charges = fees.reserve(&order).saturating_add(locate.collateral);
+
+        if charges.saturating_add(self.reserved_money) > self.purchasing_power() {
+            return false;
+        }
+
+        self.reserved_money += charges;
+
+        let reserved = if let Result::Ok(limit_order) = order.try_into() {
+            self.reserve_limit_order(&limit_order, locate.units)
         } else if let Result::Ok(market_order) = order.try_into() {
             self.reserve_market_order(&market_order)
         } else {
             false
+        };
+
+        if !reserved {
+            self.reserved_money -= charges;
         }
+
+        reserved
     }
 
     fn reserve_market_order(&mut self, order: &MarketOrder) -> bool {
         match order {
-            MarketOrder::BidOrder { .. } => true,
+            // the price is not known yet, so it sets aside everything that is left and
+            // the market caps its fills at that
+            MarketOrder::BidOrder { .. } => {
+                let available = self.purchasing_power() > self.reserved_money;
+                if available {
+                    self.reserved_money = self.purchasing_power();
+                }
+                available
+            }
             MarketOrder::AskOrder { data } => {
                 let maximum_commodity_transfer = data.size;
 
-                if maximum_commodity_transfer + self.reserved_commodity <= self.commodity {
+                if maximum_commodity_transfer <= self.free_commodity() {
                     self.reserved_commodity += maximum_commodity_transfer;
                     true
                 } else {
@@ -61,12 +167,18 @@ impl Account {
         }
     }
 
-    fn reserve_limit_order(&mut self, order: &LimitOrder) -> bool {
+    fn reserve_limit_order(&mut self, order: &LimitOrder, located: Quantity) -> bool {
         match order {
             LimitOrder::BidOrder { data } => {
-                let maximum_transaction = data.price * data.size;
+                let needed = data
+                    .price
+                    .checked_value(data.size)
+                    .and_then(|value| value.checked_add(self.reserved_money));
 
-                if maximum_transaction.as_int + self.reserved_money.as_int <= self.money.as_int {
+                if let Some(needed) = needed
+                    && needed <= self.purchasing_power()
+                {
+                    let maximum_transaction = needed - self.reserved_money;
                     self.reserved_money += maximum_transaction;
                     true
                 } else {
@@ -76,7 +188,7 @@ impl Account {
             LimitOrder::AskOrder { data } => {
                 let maximum_commodity_transfer = data.size;
 
-                if maximum_commodity_transfer + self.reserved_commodity <= self.commodity {
+                if maximum_commodity_transfer <= self.free_commodity() + located {
                     self.reserved_commodity += maximum_commodity_transfer;
                     true
                 } else {
@@ -2,10 +2,11 @@ use super::{
     account::Account,
     market::{History, MarketInfo},
 };
-use crate::orders::flat::OrderData;
+use crate::{amount::Amount, orders::flat::OrderData, quantity::Quantity, rng::SimRng};
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
-pub struct AgentId(u64);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AgentId(pub(crate) u64);
 
 impl AgentId {
     pub fn new(id: u64) -> Self {
@@ -17,7 +18,8 @@ pub trait Agent {
     type CommodityType;
     type MarketInfoType = MarketInfo<Self::CommodityType>;
 
-    fn setup(&mut self, id: AgentId, info: &Self::MarketInfoType);
+    // `rng` is the agent's own stream of the market seed, see `Market::agent_rng`
+    fn setup(&mut self, id: AgentId, info: &Self::MarketInfoType, rng: SimRng);
 
     fn produce_orders(
         &mut self,
@@ -25,4 +27,14 @@ pub trait Agent {
         info: &Self::MarketInfoType,
         history: &History,
     ) -> Vec<OrderData>;
+
+    // units it makes before quoting and what they cost, see `Market::produce`
+    fn production(
+        &mut self,
+        _account: &Account,
+        _info: &Self::MarketInfoType,
+        _history: &History,
+    ) -> Option<(Quantity, Amount)> {
+        None
+    }
 }
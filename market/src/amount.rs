@@ -1,29 +1,238 @@
-use std::ops::{Add, AddAssign, Mul, MulAssign, SubAssign};
+use std::{
+    fmt::Display,
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+    str::FromStr,
+};
 
+/// Fixed point money, `as_int` counts units of `10^-SCALE`, cents with the default
+/// scale of two. Displayed and parsed in decimal notation, "12.34" is 1234 cents.
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
-pub struct Amount {
-    pub as_int: i64, // "cents"
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Amount<const SCALE: u32 = 2> {
+    pub as_int: i64,
 }
 
-impl Amount {
+// how a division that doesn't come out even is rounded
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rounding {
+    // as integer division does
+    #[default]
+    TowardZero,
+    Down,
+    Up,
+    // halves away from zero
+    Nearest,
+}
+
+impl Rounding {
+    fn div(self, lhs: i128, rhs: i128) -> i128 {
+        let quotient = lhs / rhs;
+        let remainder = lhs % rhs;
+        if remainder == 0 {
+            return quotient;
+        }
+
+        // direction away from zero the exact result lies in
+        let away = if (lhs < 0) == (rhs < 0) { 1 } else { -1 };
+
+        match self {
+            Rounding::TowardZero => quotient,
+            Rounding::Down => quotient.min(quotient + away),
+            Rounding::Up => quotient.max(quotient + away),
+            Rounding::Nearest if 2 * remainder.abs() >= rhs.abs() => quotient + away,
+            Rounding::Nearest => quotient,
+        }
+    }
+}
+
+impl<const SCALE: u32> Amount<SCALE> {
+    // digits after the decimal point
+    pub const SCALE: u32 = SCALE;
+    pub const ONE: Self = Amount {
+        as_int: 10i64.pow(SCALE),
+    };
+    pub const MAX: Self = Amount { as_int: i64::MAX };
+    pub const MIN: Self = Amount { as_int: i64::MIN };
+
     pub fn new() -> Self {
         Amount { as_int: 0 }
     }
+
+    // whole currency units, panics where they don't fit
+    pub fn units(units: i64) -> Self {
+        Self::ONE
+            .checked_mul(units)
+            .expect("whole units out of range of the amount")
+    }
+
+    // for plots and statistics, not for bookkeeping
+    pub fn to_f64(self) -> f64 {
+        self.as_int as f64 / Self::ONE.as_int as f64
+    }
+
+    // None when the value doesn't fit
+    pub fn from_wide(wide: i128) -> Option<Self> {
+        i64::try_from(wide).ok().map(|as_int| Amount { as_int })
+    }
+
+    // the nearest amount where the value doesn't fit
+    pub fn saturating_from_wide(wide: i128) -> Self {
+        Amount {
+            as_int: wide.clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+        }
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.as_int
+            .checked_add(rhs.as_int)
+            .map(|as_int| Amount { as_int })
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.as_int
+            .checked_sub(rhs.as_int)
+            .map(|as_int| Amount { as_int })
+    }
+
+    pub fn checked_mul(self, rhs: i64) -> Option<Self> {
+        self.as_int.checked_mul(rhs).map(|as_int| Amount { as_int })
+    }
+
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Amount {
+            as_int: self.as_int.saturating_add(rhs.as_int),
+        }
+    }
+
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Amount {
+            as_int: self.as_int.saturating_sub(rhs.as_int),
+        }
+    }
+
+    pub fn saturating_mul(self, rhs: i64) -> Self {
+        Amount {
+            as_int: self.as_int.saturating_mul(rhs),
+        }
+    }
+
+    // the exact product, price times size never overflows here
+    pub fn wide_mul(self, rhs: i64) -> i128 {
+        self.as_int as i128 * rhs as i128
+    }
+
+    // None when dividing by zero or the result doesn't fit
+    pub fn div_rounded(self, rhs: i64, rounding: Rounding) -> Option<Self> {
+        self.mul_div(1, rhs, rounding)
+    }
+
+    /// `self * numerator / denominator` with an exact intermediate product, the way
+    /// shares and basis points are taken. None when `denominator` is zero or the
+    /// result doesn't fit.
+    pub fn mul_div(self, numerator: i64, denominator: i64, rounding: Rounding) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+
+        Self::from_wide(rounding.div(self.wide_mul(numerator), denominator as i128))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseAmountError {
+    Empty,
+    InvalidDigit,
+    // more digits after the decimal point than the scale has
+    TooPrecise,
+    Overflow,
+}
+
+impl Display for ParseAmountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            ParseAmountError::Empty => "no digits",
+            ParseAmountError::InvalidDigit => "invalid digit",
+            ParseAmountError::TooPrecise => "too many decimal places",
+            ParseAmountError::Overflow => "out of range",
+        };
+        write!(f, "can't parse amount: {reason}")
+    }
+}
+
+impl std::error::Error for ParseAmountError {}
+
+impl<const SCALE: u32> FromStr for Amount<SCALE> {
+    type Err = ParseAmountError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (negative, value) = match value.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(ParseAmountError::Empty);
+        }
+        if !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return Err(ParseAmountError::InvalidDigit);
+        }
+        if fraction.len() > Self::SCALE as usize {
+            return Err(ParseAmountError::TooPrecise);
+        }
+
+        // the fraction is padded to the scale, digits are validated above
+        let digits = format!("{whole}{fraction:0<width$}", width = Self::SCALE as usize);
+        let magnitude: i128 = digits.parse().map_err(|_| ParseAmountError::Overflow)?;
+
+        Self::from_wide(if negative { -magnitude } else { magnitude })
+            .ok_or(ParseAmountError::Overflow)
+    }
+}
+
+impl<const SCALE: u32> Display for Amount<SCALE> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let one = Self::ONE.as_int.unsigned_abs();
+        let magnitude = self.as_int.unsigned_abs();
+        let sign = if self.as_int < 0 { "-" } else { "" };
+
+        if SCALE == 0 {
+            return write!(f, "{sign}{magnitude}");
+        }
+        write!(
+            f,
+            "{sign}{}.{:0width$}",
+            magnitude / one,
+            magnitude % one,
+            width = Self::SCALE as usize
+        )
+    }
 }
 
-impl Default for Amount {
+impl<const SCALE: u32> Default for Amount<SCALE> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl AddAssign for Amount {
+impl<const SCALE: u32> AddAssign for Amount<SCALE> {
     fn add_assign(&mut self, rhs: Self) {
         self.as_int += rhs.as_int;
     }
 }
 
-impl Add for Amount {
+impl<const SCALE: u32> Add for Amount<SCALE> {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
@@ -33,19 +242,39 @@ impl Add for Amount {
     }
 }
 
-impl SubAssign for Amount {
+impl<const SCALE: u32> SubAssign for Amount<SCALE> {
     fn sub_assign(&mut self, rhs: Self) {
         self.as_int -= rhs.as_int;
     }
 }
 
-impl MulAssign<i64> for Amount {
+impl<const SCALE: u32> Sub for Amount<SCALE> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Amount {
+            as_int: self.as_int - rhs.as_int,
+        }
+    }
+}
+
+impl<const SCALE: u32> Neg for Amount<SCALE> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Amount {
+            as_int: -self.as_int,
+        }
+    }
+}
+
+impl<const SCALE: u32> MulAssign<i64> for Amount<SCALE> {
     fn mul_assign(&mut self, rhs: i64) {
         self.as_int *= rhs;
     }
 }
 
-impl Mul<i64> for Amount {
+impl<const SCALE: u32> Mul<i64> for Amount<SCALE> {
     type Output = Self;
     fn mul(self, rhs: i64) -> Self {
         Amount {
@@ -53,3 +282,163 @@ impl Mul<i64> for Amount {
         }
     }
 }
+
+impl<const SCALE: u32> Sum for Amount<SCALE> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::new(), Add::add)
+    }
+}
+
+impl<'a, const SCALE: u32> Sum<&'a Amount<SCALE>> for Amount<SCALE> {
+    fn sum<I: Iterator<Item = &'a Amount<SCALE>>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    // cents, as markets count
+    type Amount = super::Amount;
+
+    fn amount(as_int: i64) -> Amount {
+        Amount { as_int }
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let cases = [
+            (7, 2, [3, 3, 4, 4]),
+            (-7, 2, [-3, -4, -3, -4]),
+            (7, -2, [-3, -4, -3, -4]),
+            (5, 3, [1, 1, 2, 2]),
+            (-4, 3, [-1, -2, -1, -1]),
+            (6, 3, [2, 2, 2, 2]),
+        ];
+
+        for (lhs, rhs, expected) in cases {
+            let rounded = [
+                Rounding::TowardZero,
+                Rounding::Down,
+                Rounding::Up,
+                Rounding::Nearest,
+            ]
+            .map(|rounding| amount(lhs).div_rounded(rhs, rounding).unwrap().as_int);
+            assert_eq!(rounded, expected, "{lhs} / {rhs}");
+        }
+
+        assert_eq!(amount(1).div_rounded(0, Rounding::Up), None);
+        assert_eq!(Amount::MIN.div_rounded(-1, Rounding::Down), None);
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(Amount::MAX.checked_add(amount(1)), None);
+        assert_eq!(Amount::MIN.checked_sub(amount(1)), None);
+        assert_eq!(Amount::MAX.checked_mul(2), None);
+        assert_eq!(Amount::MAX.saturating_mul(-2), Amount::MIN);
+        assert_eq!(Amount::MAX.saturating_add(amount(1)), Amount::MAX);
+        assert_eq!(Amount::MAX.wide_mul(2), i64::MAX as i128 * 2);
+
+        // the intermediate product may overflow as long as the result fits
+        assert_eq!(
+            Amount::MAX.mul_div(3_000, 10_000, Rounding::TowardZero),
+            Some(amount((i64::MAX as i128 * 3 / 10) as i64))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "whole units out of range")]
+    fn units_out_of_range() {
+        let _ = Amount::units(i64::MAX / 10);
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(amount(5) - amount(7), amount(-2));
+        assert_eq!(-amount(5), amount(-5));
+        assert_eq!([1, 2, 3].map(amount).iter().sum::<Amount>(), amount(6));
+        assert_eq!([1, 2, 3].map(amount).into_iter().sum::<Amount>(), amount(6));
+    }
+
+    #[test]
+    fn decimal_notation() {
+        assert_eq!(Amount::ONE, amount(100));
+        assert_eq!(amount(1234).to_string(), "12.34");
+        assert_eq!(amount(-5).to_string(), "-0.05");
+        assert_eq!(amount(700).to_string(), "7.00");
+        assert_eq!(Amount::MIN.to_string(), "-92233720368547758.08");
+
+        assert_eq!("12.34".parse(), Ok(amount(1234)));
+        assert_eq!(" 12.3 ".parse(), Ok(amount(1230)));
+        assert_eq!("-.5".parse(), Ok(amount(-50)));
+        assert_eq!("+7".parse(), Ok(Amount::units(7)));
+        assert_eq!("7.".parse(), Ok(Amount::units(7)));
+
+        assert_eq!("".parse::<Amount>(), Err(ParseAmountError::Empty));
+        assert_eq!("-.".parse::<Amount>(), Err(ParseAmountError::Empty));
+        assert_eq!(
+            "1.2.3".parse::<Amount>(),
+            Err(ParseAmountError::InvalidDigit)
+        );
+        assert_eq!("1e3".parse::<Amount>(), Err(ParseAmountError::InvalidDigit));
+        assert_eq!("--1".parse::<Amount>(), Err(ParseAmountError::InvalidDigit));
+        assert_eq!("0.001".parse::<Amount>(), Err(ParseAmountError::TooPrecise));
+        assert_eq!(
+            "92233720368547758.08".parse::<Amount>(),
+            Err(ParseAmountError::Overflow)
+        );
+        assert_eq!("-92233720368547758.08".parse(), Ok(Amount::MIN));
+    }
+
+    #[test]
+    fn other_scales() {
+        let mills: super::Amount<3> = "1.5".parse().unwrap();
+        assert_eq!(mills.as_int, 1500);
+        assert_eq!(mills.to_string(), "1.500");
+        assert_eq!(super::Amount::<3>::ONE.as_int, 1000);
+
+        let whole: super::Amount<0> = "12".parse().unwrap();
+        assert_eq!(whole.as_int, 12);
+        assert_eq!(whole.to_string(), "12");
+        assert_eq!(
+            "1.5".parse::<super::Amount<0>>(),
+            Err(ParseAmountError::TooPrecise)
+        );
+    }
+
+    #[quickcheck]
+    fn display_round_trips(as_int: i64) -> bool {
+        amount(as_int).to_string().parse() == Ok(amount(as_int))
+    }
+
+    #[quickcheck]
+    fn checked_mul_is_exact_or_none(price: i64, size: i64) -> bool {
+        match amount(price).checked_mul(size) {
+            Some(product) => product.as_int as i128 == amount(price).wide_mul(size),
+            None => Amount::from_wide(amount(price).wide_mul(size)).is_none(),
+        }
+    }
+
+    #[quickcheck]
+    fn rounding_brackets_the_quotient(lhs: i64, rhs: i64) -> bool {
+        if rhs == 0 || (lhs == i64::MIN && rhs == -1) {
+            return true;
+        }
+
+        let exact = |rounding| amount(lhs).div_rounded(rhs, rounding).unwrap().as_int as i128;
+        let (down, up) = (exact(Rounding::Down), exact(Rounding::Up));
+        let (lhs, rhs) = (lhs as i128, rhs as i128);
+
+        // down * rhs <= lhs <= up * rhs for positive rhs, flipped otherwise
+        let brackets = if rhs > 0 {
+            down * rhs <= lhs && lhs <= up * rhs
+        } else {
+            down * rhs >= lhs && lhs >= up * rhs
+        };
+
+        brackets && up - down <= 1
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    account::Account,
+    agent::{Agent, AgentId},
+    amount::Amount,
+    market::MarketInfo,
+};
+
+// where the holdings of a leaving agent end up
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Settlement {
+    #[default]
+    ToMarket,
+    ToAgent(AgentId),
+}
+
+/// An agent is declared bankrupt once its equity at the reference price stays
+/// below `threshold` for `steps` consecutive checks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BankruptcyPolicy {
+    pub threshold: Amount,
+    pub steps: u64,
+    pub settlement: Settlement,
+}
+
+impl BankruptcyPolicy {
+    pub fn is_valid(&self) -> bool {
+        // a threshold above zero would retire solvent agents
+        self.steps >= 1 && self.threshold.as_int <= 0
+    }
+}
+
+impl Default for BankruptcyPolicy {
+    fn default() -> Self {
+        // negative equity for a single step
+        Self {
+            threshold: Amount::new(),
+            steps: 1,
+            settlement: Default::default(),
+        }
+    }
+}
+
+/// Replaces agents retired by a bankruptcy check. Gets the retired agent and its
+/// last account, may return a replacement to register along with its account.
+pub type Spawner<T> = Box<
+    dyn FnMut(
+        AgentId,
+        &Account,
+    ) -> Option<(
+        Box<dyn Agent<CommodityType = T, MarketInfoType = MarketInfo<T>>>,
+        Account,
+    )>,
+>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::Account,
+        credit::CreditPolicy,
+        quantity::Quantity,
+        test_util::{self, try_trade},
+    };
+
+    #[test]
+    fn deregistration_cancels_orders_and_transfers_holdings() {
+        let mut market = test_util::market("bankruptcy");
+        let leaving = market.register_with_acc(Account {
+            money: Amount { as_int: 100 },
+            commodity: Quantity::new(5),
+            ..Default::default()
+        });
+        let heir = market.register_with_default_acc();
+
+        for data in ["A:0.10:5", "B:0.10:5"] {
+            let order = market.create_orders(&leaving, &[data.try_into().unwrap()])[0].unwrap();
+            assert_eq!(market.submit_order(&leaving, order), None);
+        }
+        assert_eq!(market.all_orders().len(), 2);
+
+        let last = market
+            .deregister(leaving, Settlement::ToAgent(heir))
+            .unwrap();
+        assert_eq!(last.money, Amount { as_int: 100 });
+        assert_eq!(last.reserved_money, Amount::new());
+
+        assert!(market.all_orders().is_empty());
+        assert!(market.is_retired(&leaving));
+        assert_eq!(market.account(leaving), None);
+        assert_eq!(market.deregister(leaving, Settlement::ToMarket), None);
+
+        let heir = market.account(heir).unwrap();
+        assert_eq!(heir.money, Amount { as_int: 100 });
+        assert_eq!(heir.commodity, Quantity::new(5));
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    fn negative_equity_for_k_steps() {
+        let mut market = test_util::market("bankruptcy")
+            .with_credit(CreditPolicy::default())
+            .with_bankruptcy(BankruptcyPolicy {
+                steps: 2,
+                ..Default::default()
+            });
+
+        let debtor = market.register_with_acc(Account {
+            credit_limit: Amount { as_int: 100 },
+            ..Default::default()
+        });
+        let seller = market.register_with_acc(Account {
+            commodity: Quantity::new(10),
+            ..Default::default()
+        });
+        let before = market.total_money();
+
+        try_trade(&mut market, &[(seller, "A:0.10:10"), (debtor, "B:0.10:10")]);
+
+        assert!(market.check_bankruptcies(Amount { as_int: 10 }).is_empty());
+        assert!(market.check_bankruptcies(Amount { as_int: 5 }).is_empty());
+        // recovery resets the count
+        assert!(market.check_bankruptcies(Amount { as_int: 10 }).is_empty());
+        assert!(market.check_bankruptcies(Amount { as_int: 5 }).is_empty());
+
+        let retired = market.check_bankruptcies(Amount { as_int: 5 });
+        assert_eq!(retired.len(), 1);
+        assert_eq!(retired[0].0, debtor);
+        assert_eq!(retired[0].1.dept, Amount { as_int: 100 });
+
+        // the market keeps the commodity and writes off the debt
+        assert!(market.is_retired(&debtor));
+        assert_eq!(market.market_account().commodity, Quantity::new(10));
+        assert_eq!(market.market_account().money, Amount { as_int: -100 });
+        assert_eq!(market.total_money(), before);
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    fn validity() {
+        assert!(BankruptcyPolicy::default().is_valid());
+        assert!(
+            !BankruptcyPolicy {
+                steps: 0,
+                ..Default::default()
+            }
+            .is_valid()
+        );
+        assert!(
+            !BankruptcyPolicy {
+                threshold: Amount { as_int: 1 },
+                ..Default::default()
+            }
+            .is_valid()
+        );
+    }
+}
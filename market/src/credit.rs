@@ -0,0 +1,245 @@
+use crate::{
+    account::Account,
+    amount::{Amount, Rounding},
+    fees::{bps_of, ratio_bps},
+    orders::flat::{Order, OrderData, OrderSide},
+    quantity::Quantity,
+};
+
+/// Terms of the credit the market extends to agents up to their `Account::credit_limit`.
+/// Ratios compare collateral, commodity valued at the reference price plus money,
+/// against the outstanding debt and are expressed in basis points.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CreditPolicy {
+    // charged on the outstanding debt every step
+    pub interest_bps: i64,
+    // below this ratio the debtor is warned
+    pub margin_call_bps: i64,
+    // below this ratio the collateral is sold off
+    pub maintenance_bps: i64,
+}
+
+impl CreditPolicy {
+    pub fn is_valid(&self) -> bool {
+        self.interest_bps >= 0
+            && self.maintenance_bps >= 0
+            && self.margin_call_bps >= self.maintenance_bps
+    }
+
+    pub fn interest(&self, account: &Account) -> Amount {
+        bps_of(account.dept, self.interest_bps)
+    }
+
+    pub fn collateral(&self, account: &Account, reference_price: Amount) -> Amount {
+        account.equity(reference_price).saturating_add(account.dept)
+    }
+
+    pub fn margin_call(&self, account: &Account, reference_price: Amount) -> Option<MarginCall> {
+        if account.dept.as_int <= 0 {
+            return None;
+        }
+
+        let collateral = self.collateral(account, reference_price);
+        let ratio_bps = ratio_bps(collateral.as_int, account.dept.as_int);
+
+        if ratio_bps >= self.margin_call_bps {
+            return None;
+        }
+
+        Some(MarginCall {
+            collateral,
+            debt: account.dept,
+            ratio_bps,
+            liquidation: None,
+        })
+    }
+
+    /// Commodity to sell so that the proceeds at the reference price cover the debt.
+    pub fn liquidation_order(&self, account: &Account, reference_price: Amount) -> OrderData {
+        let free_commodity = account.free_commodity();
+        let size = match account
+            .dept
+            .div_rounded(reference_price.as_int, Rounding::Up)
+        {
+            Some(units) if reference_price.as_int > 0 => Quantity::new(units.as_int),
+            _ => free_commodity,
+        };
+
+        OrderData {
+            side: OrderSide::Ask,
+            price: None,
+            size: size.min(free_commodity),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MarginCall {
+    pub collateral: Amount,
+    pub debt: Amount,
+    pub ratio_bps: i64,
+    // market ask placed on behalf of the debtor if the maintenance ratio was breached
+    pub liquidation: Option<Order>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::AgentId,
+        fees::BPS,
+        market::Market,
+        test_util::{self, try_trade},
+    };
+
+    fn market(policy: CreditPolicy) -> (Market<()>, AgentId, AgentId) {
+        let mut market = test_util::market("credit").with_credit(policy);
+
+        let borrower = market.register_with_acc(Account {
+            money: Amount { as_int: 50 },
+            credit_limit: Amount { as_int: 100 },
+            ..Default::default()
+        });
+        let seller = market.register_with_acc(Account {
+            commodity: Quantity::new(20),
+            ..Default::default()
+        });
+
+        (market, borrower, seller)
+    }
+
+    #[test]
+    fn buys_on_credit_up_to_the_limit() {
+        let (mut market, borrower, seller) = market(Default::default());
+
+        let rejected = try_trade(
+            &mut market,
+            &[
+                (seller, "A:0.10:20"),
+                (borrower, "B:0.10:15"),
+                (borrower, "B:0.10:1"),
+            ],
+        );
+        assert_eq!(rejected[1], None);
+        assert!(rejected[2].is_some());
+
+        let account = market.account(borrower).unwrap();
+        assert_eq!(account.money, Amount::new());
+        assert_eq!(account.dept, Amount { as_int: 100 });
+        assert_eq!(account.commodity, Quantity::new(15));
+        assert_eq!(market.market_account().money, Amount { as_int: -100 });
+
+        // proceeds of a sale go towards the debt first
+        try_trade(&mut market, &[(seller, "B:0.10:3"), (borrower, "A:0.10:3")]);
+        let account = market.account(borrower).unwrap();
+        assert_eq!(account.dept, Amount { as_int: 70 });
+        assert_eq!(account.money, Amount::new());
+        assert_eq!(market.market_account().money, Amount { as_int: -70 });
+    }
+
+    #[test]
+    fn market_bids_stay_within_the_credit_line() {
+        let (mut market, borrower, seller) = market(Default::default());
+        let cashless = market.register_with_acc(Account {
+            money: Amount { as_int: 30 },
+            ..Default::default()
+        });
+
+        // a market bid can't know its price, it fills only as far as the money goes
+        try_trade(&mut market, &[(seller, "A:0.10:5"), (cashless, "B:5")]);
+        let account = market.account(cashless).unwrap();
+        assert_eq!(account.commodity, Quantity::new(3));
+        assert_eq!(account.money, Amount::new());
+        assert_eq!(account.dept, Amount::new());
+
+        // and nothing more is accepted once nothing is left
+        let rejected = try_trade(&mut market, &[(seller, "A:0.10:1"), (cashless, "B:1")]);
+        assert!(rejected[1].is_some());
+
+        // unused credit counts, the overdraft is booked as dept
+        try_trade(&mut market, &[(seller, "A:0.10:16"), (borrower, "B:20")]);
+        let account = market.account(borrower).unwrap();
+        assert_eq!(account.commodity, Quantity::new(15));
+        assert_eq!(account.money, Amount::new());
+        assert_eq!(account.dept, Amount { as_int: 100 });
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    fn interest_accrues_on_debt() {
+        let (mut market, borrower, seller) = market(CreditPolicy {
+            interest_bps: 1000,
+            ..Default::default()
+        });
+
+        try_trade(
+            &mut market,
+            &[(seller, "A:0.10:10"), (borrower, "B:0.10:10")],
+        );
+        market.accrue_interest();
+        market.accrue_interest();
+
+        assert_eq!(
+            market.account(borrower).unwrap().dept,
+            Amount { as_int: 50 + 5 + 5 }
+        );
+    }
+
+    #[test]
+    fn large_collateral_saturates_the_ratio() {
+        let policy = CreditPolicy {
+            margin_call_bps: i64::MAX,
+            ..Default::default()
+        };
+        let account = Account {
+            commodity: Quantity::new(1_000_000),
+            dept: Amount { as_int: 1 },
+            ..Default::default()
+        };
+
+        let call = policy.margin_call(&account, Amount::units(1_000_000_000));
+        assert_eq!(call.map(|call| call.ratio_bps), None);
+        let call = policy.margin_call(&account, Amount::units(1));
+        assert_eq!(call.map(|call| call.ratio_bps), Some(100_000_000 * BPS));
+    }
+
+    #[test]
+    fn margin_call_and_liquidation() {
+        let (mut market, borrower, seller) = market(CreditPolicy {
+            interest_bps: 0,
+            margin_call_bps: 15000,
+            maintenance_bps: 11000,
+        });
+
+        try_trade(
+            &mut market,
+            &[(seller, "A:0.10:15"), (borrower, "B:0.10:15")],
+        );
+        assert_eq!(
+            market.account(borrower).unwrap().dept,
+            Amount { as_int: 100 }
+        );
+
+        // collateral of 15 * 9 = 135 against a debt of 100
+        let calls = market.check_margins(Amount { as_int: 9 });
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, borrower);
+        assert_eq!(calls[0].1.ratio_bps, 13500);
+        assert_eq!(calls[0].1.liquidation, None);
+        market.clear_reserves_and_orders();
+
+        // collateral of 15 * 7 = 105 against a debt of 100
+        let calls = market.check_margins(Amount { as_int: 7 });
+        let liquidation = calls[0].1.liquidation.unwrap();
+        assert_eq!(liquidation.side, OrderSide::Ask);
+        assert_eq!(liquidation.size, Quantity::new(15));
+
+        try_trade(&mut market, &[(seller, "B:0.07:15")]);
+        let account = market.account(borrower).unwrap();
+        assert_eq!(account.commodity, Quantity::ZERO);
+        assert_eq!(account.dept, Amount::new());
+        assert_eq!(account.money, Amount { as_int: 5 });
+    }
+}
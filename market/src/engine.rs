@@ -0,0 +1,354 @@
+use crate::{
+    amount::Amount,
+    order_book::{OrderBook, Transaction},
+    orders::flat::{Order, OrderData, OrderSide},
+    quantity::Quantity,
+};
+
+/// Resting orders by price, best first, with the market orders kept apart.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookSnapshot {
+    pub bids: Vec<(Amount, Quantity)>,
+    pub asks: Vec<(Amount, Quantity)>,
+    pub market_bids: Quantity,
+    pub market_asks: Quantity,
+}
+
+impl BookSnapshot {
+    pub fn from_orders(orders: &[Order]) -> Self {
+        let mut snapshot = BookSnapshot::default();
+
+        for order in orders {
+            let (levels, market) = match order.side {
+                OrderSide::Bid => (&mut snapshot.bids, &mut snapshot.market_bids),
+                OrderSide::Ask => (&mut snapshot.asks, &mut snapshot.market_asks),
+            };
+
+            match order.price {
+                None => *market += order.size,
+                Some(price) => match levels.iter_mut().find(|(p, _)| *p == price) {
+                    Some((_, size)) => *size += order.size,
+                    None => levels.push((price, order.size)),
+                },
+            }
+        }
+
+        snapshot
+            .bids
+            .sort_by_key(|(price, _)| std::cmp::Reverse(*price));
+        snapshot.asks.sort_by_key(|(price, _)| *price);
+        snapshot
+    }
+
+    pub fn best_bid(&self) -> Option<Amount> {
+        self.bids.first().map(|(price, _)| *price)
+    }
+
+    pub fn best_ask(&self) -> Option<Amount> {
+        self.asks.first().map(|(price, _)| *price)
+    }
+}
+
+/// What the market needs from an order book. The market keeps the accounts, reserves
+/// and fees, an engine only decides which orders meet and at what price.
+pub trait MatchingEngine {
+    // hands out the next order id, `None` for a non-positive price or size
+    fn new_order_checked(&self, data: OrderData) -> Option<Order>;
+
+    fn add_order(&mut self, order: Order);
+
+    fn cancel_order(&mut self, id: u64) -> Option<Order>;
+
+    // one round of matching, `prev_market_price` is the price the last step closed at
+    fn match_step(&mut self, prev_market_price: Option<Amount>) -> Vec<Transaction>;
+
+    fn all_orders(&self) -> Vec<Order>;
+
+    fn clear_orders(&mut self);
+
+    fn snapshot(&self) -> BookSnapshot {
+        BookSnapshot::from_orders(&self.all_orders())
+    }
+}
+
+impl MatchingEngine for OrderBook {
+    fn new_order_checked(&self, data: OrderData) -> Option<Order> {
+        OrderBook::new_order_checked(self, data)
+    }
+
+    fn add_order(&mut self, order: Order) {
+        OrderBook::add_order(self, order)
+    }
+
+    fn cancel_order(&mut self, id: u64) -> Option<Order> {
+        OrderBook::cancel_order(self, id)
+    }
+
+    // market orders meet the book first, what is left of them trades at the price
+    // they set or the previous one, then the limit orders cross
+    fn match_step(&mut self, prev_market_price: Option<Amount>) -> Vec<Transaction> {
+        let primary = self.match_all_market(None);
+
+        let market_price = if primary.is_empty() {
+            None
+        } else {
+            Some(
+                primary
+                    .iter()
+                    .map(|tr| (tr.ask_gain + tr.bid_loss).as_int / 2)
+                    .sum(),
+            )
+        }
+        .map(|as_int| Amount { as_int });
+
+        let secondary = self.match_all_market(market_price.or(prev_market_price));
+        let limit = self.match_all_limit();
+
+        [primary, secondary, limit].concat()
+    }
+
+    fn all_orders(&self) -> Vec<Order> {
+        OrderBook::all_orders(self)
+    }
+
+    fn clear_orders(&mut self) {
+        OrderBook::clear_orders(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::{
+        account::Account,
+        agent::{Agent, AgentId},
+        market::{History, Market, MarketInfo},
+        rng::SimRng,
+    };
+
+    // uniform price auction over the limit orders, market orders are left resting
+    #[derive(Clone, Default)]
+    struct CallAuction {
+        orders: Vec<Order>,
+        id: Cell<u64>,
+    }
+
+    impl CallAuction {
+        fn volume_at(&self, price: Amount) -> Quantity {
+            let side = |side, crosses: &dyn Fn(Amount) -> bool| {
+                self.orders
+                    .iter()
+                    .filter(|o| o.side == side && o.price.is_some_and(crosses))
+                    .map(|o| o.size)
+                    .sum::<Quantity>()
+            };
+
+            side(OrderSide::Bid, &|p| p >= price).min(side(OrderSide::Ask, &|p| p <= price))
+        }
+    }
+
+    impl MatchingEngine for CallAuction {
+        fn new_order_checked(&self, data: OrderData) -> Option<Order> {
+            if data.price.is_some_and(|x| x.as_int <= 0) || !data.size.is_positive() {
+                return None;
+            }
+
+            let id = self.id.replace(self.id.get() + 1);
+            Some(Order {
+                timestamp: 0,
+                id,
+                side: data.side,
+                price: data.price,
+                size: data.size,
+            })
+        }
+
+        fn add_order(&mut self, order: Order) {
+            self.orders.push(order);
+        }
+
+        fn cancel_order(&mut self, id: u64) -> Option<Order> {
+            let index = self.orders.iter().position(|o| o.id == id)?;
+            Some(self.orders.remove(index))
+        }
+
+        fn match_step(&mut self, _prev_market_price: Option<Amount>) -> Vec<Transaction> {
+            let Some(price) = self
+                .orders
+                .iter()
+                .flat_map(|o| o.price)
+                .filter(|&p| self.volume_at(p).is_positive())
+                .max_by_key(|&p| (self.volume_at(p), -p.as_int))
+            else {
+                return Vec::new();
+            };
+
+            let mut bids: Vec<Order> = (self.orders.iter())
+                .filter(|o| o.side == OrderSide::Bid && o.price.is_some_and(|p| p >= price))
+                .copied()
+                .collect();
+            let mut asks: Vec<Order> = (self.orders.iter())
+                .filter(|o| o.side == OrderSide::Ask && o.price.is_some_and(|p| p <= price))
+                .copied()
+                .collect();
+            bids.sort_by_key(|o| (std::cmp::Reverse(o.price), o.id));
+            asks.sort_by_key(|o| (o.price, o.id));
+
+            let mut transactions = Vec::new();
+            let (mut b, mut a) = (0, 0);
+            while b < bids.len() && a < asks.len() {
+                let size = bids[b].size.min(asks[a].size);
+                transactions.push(Transaction {
+                    bid_id: bids[b].id,
+                    ask_id: asks[a].id,
+                    size,
+                    bid_loss: price * size,
+                    ask_gain: price * size,
+                    ..Default::default()
+                });
+
+                bids[b].size -= size;
+                asks[a].size -= size;
+                b += (!bids[b].size.is_positive()) as usize;
+                a += (!asks[a].size.is_positive()) as usize;
+            }
+
+            // crossing orders keep what wasn't filled
+            let crossed: Vec<Order> = bids.into_iter().chain(asks).collect();
+            self.orders.retain_mut(|order| {
+                if let Some(o) = crossed.iter().find(|o| o.id == order.id) {
+                    order.size = o.size;
+                }
+                order.size.is_positive()
+            });
+
+            transactions
+        }
+
+        fn all_orders(&self) -> Vec<Order> {
+            self.orders.clone()
+        }
+
+        fn clear_orders(&mut self) {
+            self.orders.clear();
+        }
+    }
+
+    // quotes from a private value, the same every step
+    struct Trader {
+        side: OrderSide,
+        value: i64,
+    }
+
+    impl Agent for Trader {
+        type CommodityType = ();
+
+        fn setup(&mut self, _id: AgentId, _info: &MarketInfo<()>, _rng: SimRng) {}
+
+        fn produce_orders(
+            &mut self,
+            _account: &Account,
+            _info: &MarketInfo<()>,
+            _history: &History,
+        ) -> Vec<OrderData> {
+            vec![OrderData {
+                side: self.side,
+                price: Some(Amount { as_int: self.value }),
+                size: Quantity::new(1),
+            }]
+        }
+    }
+
+    fn run<Engine: MatchingEngine>(mut market: Market<(), Engine>) -> Vec<Vec<Transaction>> {
+        let agents: Vec<_> = [
+            (OrderSide::Bid, 60),
+            (OrderSide::Bid, 50),
+            (OrderSide::Bid, 40),
+        ]
+        .into_iter()
+        .chain([
+            (OrderSide::Ask, 35),
+            (OrderSide::Ask, 45),
+            (OrderSide::Ask, 55),
+        ])
+        .map(|(side, value)| {
+            let id = market.register_with_acc(Account {
+                commodity: Quantity::new(10),
+                money: Amount::units(10),
+                ..Default::default()
+            });
+            let agent: Market<()>::AgentRefType = RefCell::new(Box::new(Trader { side, value }));
+            (id, agent)
+        })
+        .collect();
+
+        let mut history = History::default();
+        (1..=3)
+            .map(|step| {
+                market.start_step(step);
+                let rejected_orders = market.agents_submit_orders(&agents, &history);
+                let transactions = market.process_submitted_orders(history.market_price());
+                market.clear_reserves_and_orders();
+                assert!(market.audit().is_empty());
+
+                history = History {
+                    step,
+                    transactions: transactions.clone(),
+                    rejected_orders,
+                    ..Default::default()
+                };
+                transactions
+            })
+            .collect()
+    }
+
+    fn info() -> MarketInfo<()> {
+        MarketInfo {
+            name: "engine".to_owned(),
+            commodity: (),
+        }
+    }
+
+    #[test]
+    fn same_agents_run_against_either_engine() {
+        let continuous = run(Market::new(info()));
+        let auction = run(Market::with_engine(info(), CallAuction::default()));
+
+        for (continuous, auction) in continuous.iter().zip(&auction) {
+            let volume = |ts: &[Transaction]| ts.iter().map(|t| t.size).sum::<Quantity>();
+            assert_eq!(volume(continuous), Quantity::new(2));
+            assert_eq!(volume(auction), Quantity::new(2));
+
+            // every fill of an auction clears at the same price
+            assert!(auction.iter().all(|t| t.bid_loss == auction[0].bid_loss));
+            assert!(auction.iter().all(|t| t.diff == Amount::new()));
+        }
+        assert!(continuous.iter().flatten().any(|t| t.diff != Amount::new()));
+    }
+
+    #[test]
+    fn snapshot_aggregates_levels() {
+        let mut book = OrderBook::default();
+        for data in [
+            "B:0.40:2", "B:0.50:1", "B:0.40:3", "A:0.60:1", "A:0.55:2", "A:4",
+        ] {
+            let order = book.new_order(data.parse().unwrap());
+            MatchingEngine::add_order(&mut book, order);
+        }
+
+        let snapshot = book.snapshot();
+        assert_eq!(
+            snapshot.bids,
+            vec![
+                (Amount { as_int: 50 }, Quantity::new(1)),
+                (Amount { as_int: 40 }, Quantity::new(5))
+            ]
+        );
+        assert_eq!(snapshot.best_ask(), Some(Amount { as_int: 55 }));
+        assert_eq!(snapshot.market_asks, Quantity::new(4));
+        assert_eq!(snapshot.market_bids, Quantity::new(0));
+    }
+}
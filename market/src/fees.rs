@@ -0,0 +1,330 @@
+use crate::{
+    agent::AgentId,
+    amount::{Amount, Rounding},
+    order_book::Transaction,
+    orders::flat::{Order, OrderSide},
+};
+
+// proportional parts are expressed in basis points of the traded value
+pub const BPS: i64 = 10_000;
+
+// share of the value in basis points, saturating where it doesn't fit
+pub(crate) fn bps_of(value: Amount, bps: i64) -> Amount {
+    value
+        .mul_div(bps, BPS, Rounding::TowardZero)
+        .unwrap_or(if (value.as_int < 0) == (bps < 0) {
+            Amount::MAX
+        } else {
+            Amount::MIN
+        })
+}
+
+// `part` as a share of `whole` in basis points, saturating where it doesn't fit
+pub(crate) fn ratio_bps(part: i64, whole: i64) -> i64 {
+    let ratio = part as i128 * BPS as i128 / whole as i128;
+    ratio.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Charge applied to one side of a trade: a fixed part per fill and
+/// a proportional part of the traded value. Negative values are rebates.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fee {
+    pub fixed: Amount,
+    pub bps: i64,
+}
+
+impl Fee {
+    pub fn new(fixed: Amount, bps: i64) -> Self {
+        Fee { fixed, bps }
+    }
+
+    pub fn fixed(fixed: Amount) -> Self {
+        Fee { fixed, bps: 0 }
+    }
+
+    pub fn proportional(bps: i64) -> Self {
+        Fee {
+            fixed: Amount::new(),
+            bps,
+        }
+    }
+
+    pub fn of(&self, value: Amount) -> Amount {
+        self.fixed.saturating_add(bps_of(value, self.bps))
+    }
+
+    fn is_valid(&self) -> bool {
+        (-BPS..=BPS).contains(&self.bps)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeeSchedule {
+    pub maker: Fee,
+    pub taker: Fee,
+
+    // levied on both legs of every trade
+    pub tax: Fee,
+    // receiver of the tax, the market account if not set
+    pub tax_account: Option<AgentId>,
+}
+
+impl FeeSchedule {
+    pub fn is_valid(&self) -> bool {
+        // proceeds of an ask always have to cover its proportional charges
+        let worst_bps = self.maker.bps.max(self.taker.bps).max(0) + self.tax.bps.max(0);
+
+        self.maker.is_valid() && self.taker.is_valid() && self.tax.is_valid() && worst_bps <= BPS
+    }
+
+    pub fn apply(&self, transaction: Transaction) -> Transaction {
+        let fee = |side| {
+            if transaction.maker == Some(side) {
+                self.maker
+            } else {
+                self.taker
+            }
+        };
+
+        Transaction {
+            bid_fee: fee(OrderSide::Bid).of(transaction.bid_loss),
+            ask_fee: fee(OrderSide::Ask).of(transaction.ask_gain),
+            bid_tax: self.tax.of(transaction.bid_loss),
+            ask_tax: self.tax.of(transaction.ask_gain),
+            ..transaction
+        }
+    }
+
+    pub fn apply_all(&self, transactions: Vec<Transaction>) -> Vec<Transaction> {
+        transactions
+            .into_iter()
+            .map(|trns| self.apply(trns))
+            .collect()
+    }
+
+    /// Money an order has to set aside to pay for its charges. Every unit may end up
+    /// filled separately, so the fixed parts are counted once per unit.
+    /// Bids also cover the proportional parts of their value, asks pay those from proceeds.
+    /// A market bid has no value up front, the market caps its fills, charges included,
+    /// at what its bidder can pay.
+    pub fn reserve(&self, order: &Order) -> Amount {
+        let fixed = self.maker.fixed.max(self.taker.fixed).max(Amount::new())
+            + self.tax.fixed.max(Amount::new());
+        let fixed = fixed.saturating_value(order.size);
+
+        match (order.side, order.price) {
+            (OrderSide::Bid, Some(price)) => {
+                let bps = self.maker.bps.max(self.taker.bps).max(0) + self.tax.bps.max(0);
+                let value = price.saturating_value(order.size);
+
+                fixed.saturating_add(bps_of(value, bps))
+            }
+            _ => fixed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::Account,
+        bankruptcy::Settlement,
+        market::Market,
+        orders::flat::OrderData,
+        quantity::Quantity,
+        test_util::{self, trade},
+    };
+
+    // the tax collector is registered last, after the bidder and the asker
+    const TAX_COLLECTOR: AgentId = AgentId(2);
+
+    fn market(fees: FeeSchedule) -> (Market<()>, AgentId, AgentId) {
+        let mut market = test_util::market("fees");
+
+        let bidder = market.register_with_acc(Account {
+            money: Amount { as_int: 1000 },
+            ..Default::default()
+        });
+        let asker = market.register_with_acc(Account {
+            commodity: Quantity::new(10),
+            money: Amount { as_int: 100 },
+            ..Default::default()
+        });
+        assert_eq!(market.register_with_default_acc(), TAX_COLLECTOR);
+
+        (market.with_fees(fees), bidder, asker)
+    }
+
+    fn taxed() -> FeeSchedule {
+        FeeSchedule {
+            tax: Fee::proportional(100),
+            tax_account: Some(TAX_COLLECTOR),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn maker_taker_and_tax() {
+        let (mut market, bidder, asker) = market(FeeSchedule {
+            maker: Fee::new(Amount { as_int: 1 }, -100),
+            taker: Fee::new(Amount { as_int: 2 }, 200),
+            ..taxed()
+        });
+
+        // the ask rests first and makes, the bid takes
+        let transactions = trade(&mut market, &[(asker, "A:1.00:5"), (bidder, "B:1.00:5")]);
+
+        assert_eq!(transactions.len(), 1);
+        let trns = transactions[0];
+        assert_eq!(trns.maker, Some(OrderSide::Ask));
+        assert_eq!(trns.bid_fee, Amount { as_int: 2 + 10 });
+        assert_eq!(trns.ask_fee, Amount { as_int: 1 - 5 });
+        assert_eq!(trns.bid_tax, Amount { as_int: 5 });
+        assert_eq!(trns.ask_tax, Amount { as_int: 5 });
+
+        let bidder = market.account(bidder).unwrap();
+        let asker = market.account(asker).unwrap();
+        let collector = market.account(TAX_COLLECTOR).unwrap();
+
+        assert_eq!(
+            bidder.money,
+            Amount {
+                as_int: 1000 - 500 - 12 - 5
+            }
+        );
+        assert_eq!(bidder.commodity, Quantity::new(5));
+        assert_eq!(
+            asker.money,
+            Amount {
+                as_int: 100 + 500 + 4 - 5
+            }
+        );
+        assert_eq!(asker.commodity, Quantity::new(5));
+        assert_eq!(collector.money, Amount { as_int: 10 });
+    }
+
+    #[test]
+    fn market_collects_tax_of_a_retired_collector() {
+        let (mut market, bidder, asker) = market(taxed());
+        assert!(
+            market
+                .deregister(TAX_COLLECTOR, Settlement::ToMarket)
+                .is_some()
+        );
+
+        trade(&mut market, &[(asker, "A:1.00:5"), (bidder, "B:1.00:5")]);
+
+        assert_eq!(market.market_account().money, Amount { as_int: 10 });
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "tax collector")]
+    fn tax_collector_has_to_be_registered() {
+        let _ = test_util::market("fees").with_fees(taxed());
+    }
+
+    #[test]
+    fn charges_are_reserved() {
+        let (mut market, bidder, _) = market(FeeSchedule {
+            taker: Fee::new(Amount { as_int: 1 }, 1000),
+            ..Default::default()
+        });
+
+        // 900 for the goods, 90 proportional and 10 fixed for up to 10 fills
+        let order = market.create_orders(&bidder, &["B:0.90:10".try_into().unwrap()])[0].unwrap();
+        assert_eq!(market.submit_order(&bidder, order), None);
+
+        let order = market.create_orders(&bidder, &["B:0.01:1".try_into().unwrap()])[0].unwrap();
+        assert_eq!(market.submit_order(&bidder, order), Some(order));
+    }
+
+    #[test]
+    fn market_bids_pay_their_charges_within_the_money() {
+        let (mut market, bidder, asker) = market(FeeSchedule {
+            taker: Fee::proportional(1000),
+            ..taxed()
+        });
+
+        // every unit costs 100 for the goods, 10 taker fee and 1 tax
+        let transactions = trade(&mut market, &[(asker, "A:1.00:10"), (bidder, "B:10")]);
+
+        assert_eq!(transactions.len(), 1);
+        let trns = transactions[0];
+        assert_eq!(trns.size, Quantity::new(9));
+        assert_eq!(trns.bid_fee, Amount { as_int: 90 });
+        assert_eq!(trns.bid_tax, Amount { as_int: 9 });
+
+        let bidder = market.account(bidder).unwrap();
+        assert_eq!(bidder.money, Amount { as_int: 1 });
+        assert_eq!(bidder.commodity, Quantity::new(9));
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    fn asks_reserve_fixed_charges() {
+        let (mut market, _, asker) = market(FeeSchedule {
+            taker: Fee::fixed(Amount { as_int: 20 }),
+            ..Default::default()
+        });
+
+        let order = market.create_orders(&asker, &["A:0.01:5".try_into().unwrap()])[0].unwrap();
+        assert_eq!(market.submit_order(&asker, order), None);
+
+        let order = market.create_orders(&asker, &["A:0.01:1".try_into().unwrap()])[0].unwrap();
+        assert_eq!(market.submit_order(&asker, order), Some(order));
+    }
+
+    #[test]
+    fn overflowing_orders_are_rejected() {
+        let (mut market, bidder, asker) = market(FeeSchedule {
+            taker: Fee::new(Amount { as_int: 1 }, 1000),
+            ..Default::default()
+        });
+
+        let half: Amount = Amount {
+            as_int: i64::MAX / 2,
+        };
+        for (id, data) in [
+            (bidder, format!("B:{half}:3")),
+            (bidder, format!("B:{}", i64::MAX)),
+            (asker, format!("A:0.02:{}", i64::MAX)),
+        ] {
+            let data: OrderData = data.as_str().try_into().unwrap();
+            assert!(
+                !market
+                    .account(id)
+                    .unwrap()
+                    .reservable(&[&data], market.fees())
+            );
+
+            let order = market.create_orders(&id, &[data])[0].unwrap();
+            assert_eq!(market.submit_order(&id, order), Some(order));
+        }
+    }
+
+    #[test]
+    fn validity() {
+        assert!(FeeSchedule::default().is_valid());
+        assert!(
+            !FeeSchedule {
+                taker: Fee::proportional(BPS),
+                tax: Fee::proportional(1),
+                ..Default::default()
+            }
+            .is_valid()
+        );
+        assert!(
+            FeeSchedule {
+                maker: Fee::proportional(-BPS),
+                taker: Fee::proportional(BPS / 2),
+                tax: Fee::proportional(BPS / 2),
+                ..Default::default()
+            }
+            .is_valid()
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{amount::Amount, rng::SimRng};
+
+/// How the fundamental value of the commodity moves from one step to the next.
+/// Shocks are approximately normal with the given standard deviation in cents.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValueProcess {
+    RandomWalk {
+        volatility: f64,
+    },
+    // pulled back to `mean` by `speed` of the gap every step
+    MeanReverting {
+        mean: Amount,
+        speed: f64,
+        volatility: f64,
+    },
+    // constant apart from jumps to the given values at the given steps
+    Jumps {
+        schedule: Vec<(u64, Amount)>,
+    },
+}
+
+/// Fundamental value of a market, advanced once per step by `Market::start_step`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fundamental {
+    pub process: ValueProcess,
+    value: Amount,
+    step: u64,
+    rng: SimRng,
+}
+
+impl Fundamental {
+    pub fn new(process: ValueProcess, initial: Amount) -> Self {
+        Self {
+            process,
+            value: initial,
+            step: 0,
+            rng: SimRng::seed_from_u64(0),
+        }
+    }
+
+    pub fn value(&self) -> Amount {
+        self.value
+    }
+
+    pub(crate) fn reseed(&mut self, rng: SimRng) {
+        self.rng = rng;
+    }
+
+    // moves the value on to `step`, steps already reached are left as they are
+    pub fn advance(&mut self, step: u64) -> Amount {
+        while self.step < step {
+            self.step += 1;
+
+            let value = self.value.as_int as f64;
+            let next = match &self.process {
+                ValueProcess::RandomWalk { volatility } => {
+                    value + volatility * self.rng.standard_normal()
+                }
+                ValueProcess::MeanReverting {
+                    mean,
+                    speed,
+                    volatility,
+                } => {
+                    value
+                        + speed * (mean.as_int as f64 - value)
+                        + volatility * self.rng.standard_normal()
+                }
+                ValueProcess::Jumps { schedule } => schedule
+                    .iter()
+                    .rev()
+                    .find(|(at, _)| *at == self.step)
+                    .map_or(value, |(_, to)| to.as_int as f64),
+            };
+
+            // the value stays a price
+            self.value = Amount {
+                as_int: (next.round() as i64).max(1),
+            };
+        }
+
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(mut fundamental: Fundamental, steps: u64) -> Vec<i64> {
+        (1..=steps)
+            .map(|step| fundamental.advance(step).as_int)
+            .collect()
+    }
+
+    #[test]
+    fn processes_move_as_configured() {
+        let walk = Fundamental::new(
+            ValueProcess::RandomWalk { volatility: 2.0 },
+            Amount { as_int: 100 },
+        );
+        let mut other = walk.clone();
+        other.reseed(SimRng::seed_from_u64(1));
+        assert_eq!(path(walk.clone(), 50), path(walk.clone(), 50));
+        assert_ne!(path(walk, 50), path(other, 50));
+
+        // far from its mean the gap closes
+        let reverting = Fundamental::new(
+            ValueProcess::MeanReverting {
+                mean: Amount { as_int: 100 },
+                speed: 0.2,
+                volatility: 1.0,
+            },
+            Amount { as_int: 300 },
+        );
+        let reverted = path(reverting, 40);
+        assert!(
+            (90..=110).contains(reverted.last().unwrap()),
+            "{reverted:?}"
+        );
+
+        let jumps = Fundamental::new(
+            ValueProcess::Jumps {
+                schedule: vec![(3, Amount { as_int: 150 }), (5, Amount { as_int: 80 })],
+            },
+            Amount { as_int: 100 },
+        );
+        assert_eq!(path(jumps, 6), vec![100, 100, 150, 150, 80, 80]);
+    }
+
+    #[test]
+    fn advancing_to_a_reached_step_does_nothing() {
+        let mut fundamental = Fundamental::new(
+            ValueProcess::RandomWalk { volatility: 5.0 },
+            Amount { as_int: 100 },
+        );
+        let value = fundamental.advance(3);
+        assert_eq!(fundamental.advance(3), value);
+        assert_eq!(fundamental.advance(2), value);
+    }
+}
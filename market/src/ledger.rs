@@ -0,0 +1,383 @@
+use crate::{account::Account, agent::AgentId, amount::Amount, quantity::Quantity};
+
+// who a ledger entry belongs to, the outside world is the source of every injection
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Holder {
+    External,
+    Market,
+    Agent(AgentId),
+}
+
+/// Balances kept per holder. `Money`, `Collateral` and `Debt` are counted in money,
+/// `Commodity` and `Borrowed` in units. Liabilities are credited to the lender, so
+/// the debt and borrowed books of a borrower are negative and those of the market
+/// hold what it is owed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Book {
+    Money,
+    Collateral,
+    Debt,
+    Commodity,
+    Borrowed,
+}
+
+impl Book {
+    pub fn is_money(&self) -> bool {
+        matches!(self, Book::Money | Book::Collateral | Book::Debt)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Leg {
+    pub holder: Holder,
+    pub book: Book,
+}
+
+impl Holder {
+    pub fn money(self) -> Leg {
+        self.leg(Book::Money)
+    }
+
+    pub fn collateral(self) -> Leg {
+        self.leg(Book::Collateral)
+    }
+
+    pub fn debt(self) -> Leg {
+        self.leg(Book::Debt)
+    }
+
+    pub fn commodity(self) -> Leg {
+        self.leg(Book::Commodity)
+    }
+
+    pub fn borrowed(self) -> Leg {
+        self.leg(Book::Borrowed)
+    }
+
+    fn leg(self, book: Book) -> Leg {
+        Leg { holder: self, book }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PostingKind {
+    Injection,
+    Production,
+    Trade,
+    Fee,
+    Tax,
+    Interest,
+    Credit,
+    Lending,
+    Penalty,
+    Deregistration,
+}
+
+// moves `amount` out of `from` into `to`, both books count the same thing
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Posting {
+    pub step: u64,
+    pub kind: PostingKind,
+    pub from: Leg,
+    pub to: Leg,
+    pub amount: i64,
+}
+
+// the part of an account the ledger keeps track of
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Balances {
+    pub money: Amount,
+    pub collateral: Amount,
+    pub dept: Amount,
+    pub commodity: Quantity,
+    pub borrowed_commodity: Quantity,
+}
+
+impl From<&Account> for Balances {
+    fn from(account: &Account) -> Self {
+        Balances {
+            money: account.money,
+            collateral: account.collateral,
+            dept: account.dept,
+            commodity: account.commodity,
+            borrowed_commodity: account.borrowed_commodity,
+        }
+    }
+}
+
+impl Balances {
+    fn apply(&mut self, book: Book, amount: i64) {
+        match book {
+            Book::Money => self.money.as_int += amount,
+            Book::Collateral => self.collateral.as_int += amount,
+            Book::Debt => self.dept.as_int -= amount,
+            Book::Commodity => self.commodity.as_int += amount,
+            Book::Borrowed => self.borrowed_commodity.as_int -= amount,
+        }
+    }
+}
+
+/// Append-only journal of every balance change in a market.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ledger {
+    step: u64,
+    postings: Vec<Posting>,
+}
+
+impl Ledger {
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    pub fn postings_at(&self, step: u64) -> impl Iterator<Item = &Posting> {
+        self.postings
+            .iter()
+            .filter(move |posting| posting.step == step)
+    }
+
+    pub fn balances(&self, holder: Holder) -> Balances {
+        self.balances_at(holder, u64::MAX)
+    }
+
+    // balances of the holder at the end of `step`
+    pub fn balances_at(&self, holder: Holder, step: u64) -> Balances {
+        self.postings
+            .iter()
+            .take_while(|posting| posting.step <= step)
+            .fold(Balances::default(), |mut balances, posting| {
+                if posting.from.holder == holder {
+                    balances.apply(posting.from.book, -posting.amount);
+                }
+                if posting.to.holder == holder {
+                    balances.apply(posting.to.book, posting.amount);
+                }
+                balances
+            })
+    }
+
+    pub(crate) fn set_step(&mut self, step: u64) {
+        assert!(step >= self.step, "the ledger can't go back to step {step}");
+        self.step = step;
+    }
+
+    // returns the posting with a non-negative amount, None if nothing moves
+    pub(crate) fn record(
+        &mut self,
+        kind: PostingKind,
+        from: Leg,
+        to: Leg,
+        amount: i64,
+    ) -> Option<Posting> {
+        assert_eq!(
+            from.book.is_money(),
+            to.book.is_money(),
+            "{:?} and {:?} don't count the same thing",
+            from,
+            to
+        );
+
+        let (from, to, amount) = match amount {
+            0 => return None,
+            ..0 => (to, from, -amount),
+            _ => (from, to, amount),
+        };
+
+        let posting = Posting {
+            step: self.step,
+            kind,
+            from,
+            to,
+            amount,
+        };
+        self.postings.push(posting);
+        Some(posting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        credit::CreditPolicy,
+        fees::{Fee, FeeSchedule},
+        lending::LendingPolicy,
+        test_util::{self, trade},
+    };
+
+    #[test]
+    fn postings_balance() {
+        let mut ledger = Ledger::default();
+        let agent = Holder::Agent(AgentId::new(0));
+
+        ledger.record(
+            PostingKind::Injection,
+            Holder::External.money(),
+            agent.money(),
+            10,
+        );
+        ledger.set_step(1);
+        let posting = ledger
+            .record(
+                PostingKind::Trade,
+                Holder::Market.money(),
+                agent.money(),
+                -4,
+            )
+            .unwrap();
+        assert_eq!(posting.from, agent.money());
+        assert_eq!(posting.amount, 4);
+        assert_eq!(
+            ledger.record(PostingKind::Trade, agent.money(), agent.money(), 0),
+            None
+        );
+
+        assert_eq!(ledger.balances_at(agent, 0).money, Amount { as_int: 10 });
+        assert_eq!(ledger.balances(agent).money, Amount { as_int: 6 });
+        assert_eq!(ledger.balances(Holder::Market).money, Amount { as_int: 4 });
+        assert_eq!(ledger.postings_at(1).count(), 1);
+
+        let total: i64 = [Holder::External, Holder::Market, agent]
+            .into_iter()
+            .map(|holder| ledger.balances(holder).money.as_int)
+            .sum();
+        assert_eq!(total, 0);
+    }
+
+    #[test]
+    fn production_is_paid_for() {
+        let mut market = test_util::market("ledger");
+        let producer = market.register_with_acc(Account {
+            money: Amount { as_int: 50 },
+            credit_limit: Amount { as_int: 20 },
+            ..Default::default()
+        });
+
+        market.start_step(1);
+        assert!(market.produce(producer, Quantity::new(3), Amount { as_int: 60 }));
+        // only 10 of the credit line is left
+        assert!(!market.produce(producer, Quantity::new(1), Amount { as_int: 20 }));
+        assert!(market.audit().is_empty());
+
+        let account = market.account(producer).unwrap();
+        assert_eq!(account.commodity, Quantity::new(3));
+        assert_eq!(account.money, Amount::new());
+        assert_eq!(account.dept, Amount { as_int: 10 });
+
+        // the cost leaves the market, the units come into it
+        let external = market.ledger().balances(Holder::External);
+        assert_eq!(external.money, Amount { as_int: 60 - 50 });
+        assert_eq!(external.commodity, Quantity::new(-3));
+    }
+
+    #[test]
+    fn withdrawals_stay_within_free_balances() {
+        let mut market = test_util::market("ledger");
+        let seller = market.register_with_acc(Account {
+            money: Amount { as_int: 50 },
+            commodity: Quantity::new(10),
+            ..Default::default()
+        });
+
+        let order = market.create_orders(&seller, &["A:0.10:6".parse().unwrap()])[0].unwrap();
+        assert_eq!(market.submit_order(&seller, order), None);
+
+        // six units back the resting ask
+        assert!(!market.inject_commodity(seller, Quantity::new(-5)));
+        assert!(market.inject_commodity(seller, Quantity::new(-4)));
+        assert!(!market.inject_money(seller, Amount { as_int: -51 }));
+        assert!(!market.inject_money(seller, Amount::MIN));
+        assert!(market.inject_money(seller, Amount { as_int: -50 }));
+        assert!(!market.inject_money(AgentId::new(7), Amount { as_int: 1 }));
+
+        let account = market.account(seller).unwrap();
+        assert_eq!(account.commodity, Quantity::new(6));
+        assert_eq!(account.money, Amount::new());
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn books_of_different_units_dont_mix() {
+        let agent = Holder::Agent(AgentId::new(0));
+        Ledger::default().record(PostingKind::Trade, agent.money(), agent.commodity(), 1);
+    }
+
+    #[test]
+    fn market_activity_is_audited() {
+        let mut market = test_util::market("ledger")
+            .with_fees(FeeSchedule {
+                taker: Fee::fixed(Amount { as_int: 1 }),
+                ..Default::default()
+            })
+            .with_credit(CreditPolicy {
+                interest_bps: 1000,
+                ..Default::default()
+            })
+            .with_lending(LendingPolicy {
+                fee_bps: 100,
+                collateral_bps: 5000,
+            });
+        market.fund_lending_pool(Quantity::new(10));
+
+        let buyer = market.register_with_acc(Account {
+            credit_limit: Amount { as_int: 100 },
+            ..Default::default()
+        });
+        let short_seller = market.register_with_acc(Account {
+            money: Amount { as_int: 100 },
+            ..Default::default()
+        });
+
+        market.start_step(1);
+        trade(
+            &mut market,
+            &[(short_seller, "A:0.10:8"), (buyer, "B:0.10:8")],
+        );
+        market.accrue_interest();
+        market.settle_lending(Amount { as_int: 10 });
+
+        market.start_step(2);
+        market.inject_money(buyer, Amount { as_int: 40 });
+        market.inject_commodity(short_seller, Quantity::new(3));
+        market.settle_lending(Amount { as_int: 10 });
+
+        assert!(market.audit().is_empty());
+
+        let ledger = market.ledger();
+        let buyer = Holder::Agent(buyer);
+        assert_eq!(ledger.balances_at(buyer, 0), Balances::default());
+
+        let after_trade = ledger.balances_at(buyer, 1);
+        assert_eq!(after_trade.commodity, Quantity::new(8));
+        assert_eq!(after_trade.money, Amount::new());
+        // 81 drawn for the trade and the fee, 8 of interest on top
+        assert_eq!(after_trade.dept, Amount { as_int: 89 });
+        assert_eq!(ledger.balances(buyer).dept, Amount { as_int: 49 });
+
+        let short_seller = ledger.balances(Holder::Agent(short_seller));
+        assert_eq!(short_seller.borrowed_commodity, Quantity::new(5));
+        assert_eq!(short_seller.collateral, Amount { as_int: 25 });
+
+        // nothing but injections came from outside
+        let external = ledger.balances(Holder::External);
+        assert_eq!(external.money, Amount { as_int: -140 });
+        assert_eq!(external.commodity, Quantity::new(-13));
+        assert_eq!(
+            market.total_money(),
+            Amount {
+                as_int: -external.money.as_int
+            }
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    account::Account,
+    amount::Amount,
+    fees::{bps_of, ratio_bps},
+    orders::flat::{Order, OrderSide},
+    quantity::Quantity,
+};
+
+/// Terms of the securities lending facility. Borrowed commodity is collateralised
+/// with money worth `collateral_bps` of its value at the short sale price and
+/// costs `fee_bps` of its value at the reference price every step.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LendingPolicy {
+    pub fee_bps: i64,
+    pub collateral_bps: i64,
+}
+
+impl LendingPolicy {
+    pub fn is_valid(&self) -> bool {
+        self.fee_bps >= 0 && self.collateral_bps >= 0
+    }
+
+    pub fn collateral(&self, price: Amount, units: Quantity) -> Amount {
+        bps_of(price.saturating_value(units), self.collateral_bps)
+    }
+
+    pub fn borrow_fee(&self, account: &Account, reference_price: Amount) -> Amount {
+        bps_of(
+            reference_price.saturating_value(account.borrowed_commodity),
+            self.fee_bps,
+        )
+    }
+
+    /// Units an ask has to borrow beyond the free commodity of the account
+    /// together with the collateral for them. Only limit asks can be sold short.
+    pub fn locate_need(&self, account: &Account, order: &Order) -> Locate {
+        let (OrderSide::Ask, Some(price)) = (order.side, order.price) else {
+            return Locate::default();
+        };
+
+        let free_commodity = account.free_commodity().max(Quantity::ZERO);
+        let units = (order.size - free_commodity).max(Quantity::ZERO);
+
+        Locate {
+            units,
+            collateral: self.collateral(price, units),
+        }
+    }
+}
+
+// commodity located for a short sale and money to post against it
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Locate {
+    pub units: Quantity,
+    pub collateral: Amount,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShortInterest {
+    // units currently lent out
+    pub borrowed: Quantity,
+    // units the lender is willing to lend in total
+    pub lendable: Quantity,
+}
+
+impl ShortInterest {
+    pub fn available(&self) -> Quantity {
+        (self.lendable - self.borrowed).max(Quantity::ZERO)
+    }
+
+    pub fn utilisation_bps(&self) -> i64 {
+        if self.lendable == Quantity::ZERO {
+            0
+        } else {
+            ratio_bps(self.borrowed.as_int, self.lendable.as_int)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Recall {
+    // units handed back from the borrower's holdings
+    pub returned: Quantity,
+    // market bid placed on behalf of the borrower for the rest
+    pub buy_in: Option<Order>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::AgentId,
+        market::Market,
+        test_util::{self, try_trade},
+    };
+
+    fn market(policy: LendingPolicy) -> (Market<()>, AgentId, AgentId) {
+        let mut market = test_util::market("lending").with_lending(policy);
+        market.fund_lending_pool(Quantity::new(10));
+
+        let short_seller = market.register_with_acc(Account {
+            money: Amount { as_int: 100 },
+            ..Default::default()
+        });
+        let buyer = market.register_with_acc(Account {
+            money: Amount { as_int: 1000 },
+            commodity: Quantity::new(10),
+            ..Default::default()
+        });
+
+        (market, short_seller, buyer)
+    }
+
+    fn totals(market: &Market<()>) -> (Amount, Quantity) {
+        (market.total_money(), market.total_commodity())
+    }
+
+    #[test]
+    fn utilisation_of_a_large_pool() {
+        let interest = ShortInterest {
+            borrowed: Quantity::new(i64::MAX / 4),
+            lendable: Quantity::new(i64::MAX / 4 * 2),
+        };
+        assert_eq!(interest.utilisation_bps(), 5000);
+        assert_eq!(ShortInterest::default().utilisation_bps(), 0);
+    }
+
+    #[test]
+    fn no_shorts_without_facility() {
+        let mut market = test_util::market("lending");
+        let id = market.register_with_acc(Account {
+            money: Amount { as_int: 100 },
+            ..Default::default()
+        });
+
+        assert!(try_trade(&mut market, &[(id, "A:0.10:1")])[0].is_some());
+    }
+
+    #[test]
+    fn short_sale_is_located_and_collateralised() {
+        let (mut market, short_seller, buyer) = market(LendingPolicy {
+            fee_bps: 0,
+            collateral_bps: 5000,
+        });
+        let before = totals(&market);
+
+        // 8 units at 10 need 40 of collateral, more than the pool can't be located
+        let rejected = try_trade(
+            &mut market,
+            &[
+                (short_seller, "A:0.10:8"),
+                (short_seller, "A:0.10:3"),
+                (buyer, "B:0.10:8"),
+            ],
+        );
+        assert_eq!(rejected[0], None);
+        assert!(rejected[1].is_some());
+
+        let account = market.account(short_seller).unwrap();
+        assert_eq!(account.commodity, Quantity::ZERO);
+        assert_eq!(account.borrowed_commodity, Quantity::new(8));
+        assert_eq!(account.collateral, Amount { as_int: 40 });
+        assert_eq!(
+            account.money,
+            Amount {
+                as_int: 100 + 80 - 40
+            }
+        );
+        assert_eq!(
+            market.short_interest(),
+            ShortInterest {
+                borrowed: Quantity::new(8),
+                lendable: Quantity::new(10)
+            }
+        );
+        assert_eq!(totals(&market), before);
+
+        // buying back covers the short and releases the collateral
+        try_trade(
+            &mut market,
+            &[(buyer, "A:0.05:8"), (short_seller, "B:0.05:8")],
+        );
+        market.settle_lending(Amount { as_int: 5 });
+
+        let account = market.account(short_seller).unwrap();
+        assert_eq!(account.borrowed_commodity, Quantity::ZERO);
+        assert_eq!(account.collateral, Amount::new());
+        assert_eq!(account.money, Amount { as_int: 140 });
+        assert_eq!(market.short_interest().borrowed, Quantity::ZERO);
+        assert_eq!(totals(&market), before);
+    }
+
+    #[test]
+    fn borrow_fee_is_charged_per_step() {
+        let (mut market, short_seller, buyer) = market(LendingPolicy {
+            fee_bps: 100,
+            collateral_bps: 0,
+        });
+        let before = totals(&market);
+
+        try_trade(
+            &mut market,
+            &[(short_seller, "A:0.10:10"), (buyer, "B:0.10:10")],
+        );
+        market.settle_lending(Amount { as_int: 10 });
+        market.settle_lending(Amount { as_int: 20 });
+
+        let account = market.account(short_seller).unwrap();
+        assert_eq!(
+            account.money,
+            Amount {
+                as_int: 100 + 100 - 1 - 2
+            }
+        );
+        assert_eq!(totals(&market), before);
+    }
+
+    #[test]
+    fn recall_returns_holdings_and_buys_in() {
+        let (mut market, short_seller, buyer) = market(LendingPolicy {
+            fee_bps: 0,
+            collateral_bps: 10000,
+        });
+        let before = totals(&market);
+
+        try_trade(
+            &mut market,
+            &[(short_seller, "A:0.10:6"), (buyer, "B:0.10:6")],
+        );
+        try_trade(
+            &mut market,
+            &[(buyer, "A:0.10:2"), (short_seller, "B:0.10:2")],
+        );
+        assert_eq!(
+            market.account(short_seller).unwrap().commodity,
+            Quantity::new(2)
+        );
+
+        let recalls = market.recall(Quantity::new(10));
+        assert_eq!(recalls.len(), 1);
+        let (id, recall) = recalls[0];
+        assert_eq!(id, short_seller);
+        assert_eq!(recall.returned, Quantity::new(2));
+        assert_eq!(recall.buy_in.unwrap().size, Quantity::new(4));
+
+        try_trade(&mut market, &[(buyer, "A:0.12:4")]);
+        market.settle_lending(Amount { as_int: 12 });
+
+        let account = market.account(short_seller).unwrap();
+        assert_eq!(account.borrowed_commodity, Quantity::ZERO);
+        assert_eq!(account.commodity, Quantity::ZERO);
+        assert_eq!(account.collateral, Amount::new());
+        assert_eq!(
+            account.money,
+            Amount {
+                as_int: 100 + 60 - 20 - 48
+            }
+        );
+        assert_eq!(market.short_interest(), ShortInterest::default());
+        assert_eq!(totals(&market), before);
+        assert!(market.audit().is_empty());
+    }
+}
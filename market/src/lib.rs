@@ -1,14 +1,73 @@
 #![allow(incomplete_features)]
 #![feature(associated_type_defaults)]
 #![feature(inherent_associated_types)]
-#![feature(trait_alias)]
 
 pub mod account;
 pub mod agent;
 pub mod amount;
+pub mod bankruptcy;
+pub mod credit;
+pub mod engine;
+pub mod fees;
+pub mod fundamental;
+pub mod ledger;
+pub mod lending;
 pub mod market;
 pub mod order_book;
 pub mod orders;
+pub mod portfolio;
+pub mod quantity;
+pub mod replay;
+pub mod rng;
+pub mod settlement;
+pub mod snapshot;
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    use crate::{
+        agent::AgentId,
+        market::{History, Market, MarketInfo},
+        order_book::Transaction,
+        orders::flat::{Order, OrderData},
+    };
+
+    pub fn market(name: &str) -> Market<()> {
+        Market::new(MarketInfo {
+            name: name.to_owned(),
+            commodity: (),
+        })
+    }
+
+    fn submit_and_match(
+        market: &mut Market<()>,
+        orders: &[(AgentId, &str)],
+    ) -> (Vec<Option<Order>>, Vec<Transaction>) {
+        let rejected = orders
+            .iter()
+            .map(|(id, data)| {
+                let data: OrderData = (*data).try_into().unwrap();
+                let order = market.create_orders(id, &[data])[0].unwrap();
+                market.submit_order(id, order)
+            })
+            .collect();
+
+        let transactions = market.process_submitted_orders(History::default().market_price());
+        market.clear_reserves_and_orders();
+        (rejected, transactions)
+    }
+
+    // submits the orders, matches them once and clears the book, returns the rejected ones
+    pub fn try_trade(market: &mut Market<()>, orders: &[(AgentId, &str)]) -> Vec<Option<Order>> {
+        submit_and_match(market, orders).0
+    }
+
+    // like `try_trade` for orders that all have to be accepted, returns the fills
+    pub fn trade(market: &mut Market<()>, orders: &[(AgentId, &str)]) -> Vec<Transaction> {
+        let (rejected, transactions) = submit_and_match(market, orders);
+        assert!(rejected.iter().all(Option::is_none), "{rejected:?}");
+        transactions
+    }
+}
 
 #[cfg(test)]
 mod test_simulation {
@@ -19,6 +78,7 @@ mod test_simulation {
         agent::{Agent, AgentId},
         market::{History, Market, MarketInfo},
         orders::flat::OrderData,
+        rng::SimRng,
     };
 
     enum CommodityType {
@@ -30,7 +90,7 @@ mod test_simulation {
     impl Agent for ProducerAgent {
         type CommodityType = CommodityType;
 
-        fn setup(&mut self, _id: AgentId, _info: &MarketInfo<CommodityType>) {}
+        fn setup(&mut self, _id: AgentId, _info: &MarketInfo<CommodityType>, _rng: SimRng) {}
 
         fn produce_orders(
             &mut self,
@@ -39,9 +99,9 @@ mod test_simulation {
             _history: &History,
         ) -> Vec<OrderData> {
             vec![
-                "A:1:1".try_into().unwrap(),
-                "A:2:1".try_into().unwrap(),
-                "A:3:1".try_into().unwrap(),
+                "A:0.01:1".try_into().unwrap(),
+                "A:0.02:1".try_into().unwrap(),
+                "A:0.03:1".try_into().unwrap(),
             ]
         }
     }
@@ -51,7 +111,7 @@ mod test_simulation {
     impl Agent for ConsumerAgent {
         type CommodityType = CommodityType;
 
-        fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType) {}
+        fn setup(&mut self, _id: AgentId, _info: &Self::MarketInfoType, _rng: SimRng) {}
 
         fn produce_orders(
             &mut self,
@@ -59,7 +119,10 @@ mod test_simulation {
             _info: &MarketInfo<CommodityType>,
             _history: &History,
         ) -> Vec<OrderData> {
-            vec!["B:2:1".try_into().unwrap(), "B:4:1".try_into().unwrap()]
+            vec![
+                "B:0.02:1".try_into().unwrap(),
+                "B:0.04:1".try_into().unwrap(),
+            ]
         }
     }
 
@@ -77,7 +140,7 @@ mod test_simulation {
                 .into_iter()
                 .map(|agent| {
                     let id = market.register_with_default_acc();
-                    (*agent.borrow_mut()).setup(id, &market.info);
+                    (*agent.borrow_mut()).setup(id, &market.info, market.agent_rng(id));
                     (id, agent)
                 })
                 .collect()
@@ -97,6 +160,7 @@ mod test_simulation {
                 transactions,
                 rejected_orders,
                 unfulfilled_orders,
+                ..Default::default()
             };
 
             market.clear_reserves_and_orders();
@@ -105,3 +169,82 @@ mod test_simulation {
         println!("history: {}", history);
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use crate::{
+        account::Account,
+        amount::Amount,
+        market::{History, Market, MarketInfo},
+        orders::flat::OrderData,
+        quantity::Quantity,
+        settlement::SettlementPolicy,
+    };
+
+    fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn market_state_round_trips() {
+        let mut market = Market::new(MarketInfo {
+            name: "serde".to_owned(),
+            commodity: (),
+        })
+        .with_settlement(SettlementPolicy {
+            lag: 1,
+            ..Default::default()
+        });
+
+        let seller = market.register_with_acc(Account {
+            commodity: Quantity::new(10),
+            ..Default::default()
+        });
+        let buyer = market.register_with_acc(Account {
+            money: Amount::units(10),
+            ..Default::default()
+        });
+
+        let submit = |market: &mut Market<()>, orders: &[(_, &str)]| {
+            for (id, data) in orders {
+                let data: OrderData = data.parse().unwrap();
+                let order = market.create_orders(id, &[data])[0].unwrap();
+                assert_eq!(market.submit_order(id, order), None);
+            }
+        };
+
+        // a fill waiting for settlement and orders resting in the book
+        submit(&mut market, &[(seller, "A:0.50:4"), (buyer, "B:0.50:4")]);
+        let transactions = market.process_submitted_orders(None);
+        market.clear_reserves_and_orders();
+        submit(&mut market, &[(seller, "A:0.60:2"), (buyer, "B:0.40:1")]);
+
+        let json = serde_json::to_value(&market).unwrap();
+        assert_eq!(json["info"]["name"], "serde");
+        assert_eq!(json["market_account"]["money"], 0);
+
+        let mut restored: Market<()> = round_trip(&market);
+        assert_eq!(serde_json::to_value(&restored).unwrap(), json);
+        assert_eq!(restored.all_orders(), market.all_orders());
+
+        // both carry on the same way
+        for market in [&mut market, &mut restored] {
+            market.start_step(1);
+            market.settle_obligations();
+            submit(market, &[(buyer, "B:0.60:2")]);
+        }
+        assert_eq!(
+            restored.process_submitted_orders(None),
+            market.process_submitted_orders(None)
+        );
+        assert_eq!(restored.account(buyer), market.account(buyer));
+        assert!(restored.audit().is_empty());
+
+        let history = History {
+            step: 1,
+            transactions,
+            ..Default::default()
+        };
+        assert_eq!(round_trip(&history).transactions, history.transactions);
+    }
+}
@@ -1,12 +1,29 @@
-use std::{cell::RefCell, collections::HashMap, fmt::Display};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
 use crate::{
     amount::Amount,
+    bankruptcy::{BankruptcyPolicy, Settlement},
+    credit::{CreditPolicy, MarginCall},
+    engine::{BookSnapshot, MatchingEngine},
+    fees::{FeeSchedule, bps_of},
+    fundamental::Fundamental,
+    ledger::{Balances, Book, Holder, Ledger, Leg, PostingKind},
+    lending::{LendingPolicy, Recall, ShortInterest},
     order_book::{OrderBook, Transaction},
     orders::{
-        flat::{Order, OrderData},
+        flat::{Order, OrderData, OrderSide},
         limit::LimitOrder,
+        text::{OrderSpec, TimeInForce},
     },
+    portfolio::{Portfolio, PortfolioStats},
+    quantity::Quantity,
+    replay::{Event, EventLog},
+    rng::SimRng,
+    settlement::{DeliveryFailure, Obligation, SettlementPolicy},
 };
 
 use super::{
@@ -14,12 +31,25 @@ use super::{
     agent::{Agent, AgentId},
 };
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct History {
     pub step: u64,
     pub transactions: Vec<Transaction>,
     pub rejected_orders: Vec<(AgentId, Order)>,
     pub unfulfilled_orders: Vec<(AgentId, Order)>,
+    pub margin_calls: Vec<(AgentId, MarginCall)>,
+    pub short_interest: ShortInterest,
+    pub portfolios: Vec<(AgentId, PortfolioStats)>,
+    // keyed by the failing asker
+    pub failed_deliveries: Vec<(AgentId, DeliveryFailure)>,
+    // resting orders by price as the step ended, before they were cleared
+    pub book: BookSnapshot,
+    // fundamental value of the step where the market has one, it changes every
+    // step while `MarketInfo` stays the same, so agents read it here
+    pub fundamental: Option<Amount>,
+    // earlier steps, oldest first, none of them has a window of its own
+    pub window: Vec<History>,
 }
 
 impl History {
@@ -39,6 +69,40 @@ impl History {
         }
     }
 
+    // volume weighted price of a unit over the transactions of the step
+    pub fn unit_price(&self) -> Option<Amount> {
+        let (value, units) = self
+            .transactions
+            .iter()
+            .fold((0i128, 0i128), |(value, units), tr| {
+                (
+                    value + tr.ask_gain.as_int as i128 + tr.bid_loss.as_int as i128,
+                    units + tr.size.as_int as i128,
+                )
+            });
+
+        (units > 0).then(|| Amount {
+            as_int: (value / 2 / units) as i64,
+        })
+    }
+
+    // `next` with this step added to its window, which keeps at most `len` steps.
+    // the empty history before the first step is left out
+    pub fn advance(mut self, mut next: History, len: usize) -> History {
+        let mut window = std::mem::take(&mut self.window);
+        if self.step > 0 {
+            window.push(self);
+        }
+        window.drain(..window.len().saturating_sub(len));
+        next.window = window;
+        next
+    }
+
+    // the steps of the window and then this one
+    pub fn recent(&self) -> impl Iterator<Item = &History> {
+        self.window.iter().chain([self])
+    }
+
     pub fn no_transactions(&self) -> bool {
         self.transactions.is_empty()
     }
@@ -59,13 +123,51 @@ impl History {
                 .cloned()
                 .filter(|(id, _)| agent_id == id)
                 .collect(),
+            margin_calls: self
+                .margin_calls
+                .iter()
+                .cloned()
+                .filter(|(id, _)| agent_id == id)
+                .collect(),
+            short_interest: self.short_interest,
+            portfolios: self
+                .portfolios
+                .iter()
+                .cloned()
+                .filter(|(id, _)| agent_id == id)
+                .collect(),
+            failed_deliveries: self
+                .failed_deliveries
+                .iter()
+                .cloned()
+                .filter(|(id, failure)| agent_id == id || *agent_id == failure.obligation.bidder)
+                .collect(),
+            book: self.book.clone(),
+            fundamental: self.fundamental,
+            window: self
+                .window
+                .iter()
+                .map(|history| history.filter_by_agent_id(agent_id))
+                .collect(),
         }
     }
 
+    pub fn portfolio(&self, agent_id: &AgentId) -> Option<&PortfolioStats> {
+        self.portfolios
+            .iter()
+            .find(|(id, _)| id == agent_id)
+            .map(|(_, stats)| stats)
+    }
+
     pub fn clear(&mut self) {
         self.transactions.clear();
         self.rejected_orders.clear();
         self.unfulfilled_orders.clear();
+        self.margin_calls.clear();
+        self.portfolios.clear();
+        self.failed_deliveries.clear();
+        self.book = Default::default();
+        self.window.clear();
     }
 }
 
@@ -79,40 +181,405 @@ impl Display for History {
     }
 }
 
+// agent ids never get this far
+const FUNDAMENTAL_STREAM: u64 = u64::MAX;
+
 type AgentType<T> = Box<dyn Agent<CommodityType = T, MarketInfoType = MarketInfo<T>>>;
 type AgentRefType<T> = RefCell<AgentType<T>>;
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MarketInfo<CommodityType> {
     pub name: String,
     pub commodity: CommodityType,
 }
 
-pub struct Market<CommodityType> {
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Market<CommodityType, Engine = OrderBook> {
     pub info: MarketInfo<CommodityType>,
 
-    book: OrderBook,
+    book: Engine,
 
     id: RefCell<u64>,
 
     market_account: Account,
+    fees: FeeSchedule,
+    credit: CreditPolicy,
+
+    lending: Option<LendingPolicy>,
+    // units the lender is willing to lend, the unlent part is held by the market account
+    lendable: Quantity,
+    // units promised to short sellers in the current step
+    located: Quantity,
+    // money each market bid of the current step may still spend, by order id
+    budgets: HashMap<u64, Amount>,
+
+    bankruptcy: Option<BankruptcyPolicy>,
+    // consecutive checks each agent spent below the bankruptcy threshold
+    distress: HashMap<AgentId, u64>,
+    retired: HashSet<AgentId>,
 
-    pub accounts: HashMap<AgentId, Account>,
+    settlement: SettlementPolicy,
+    // fills waiting for delivery in the order they happened
+    obligations: Vec<Obligation>,
+
+    // kept for retired agents as well
+    portfolios: HashMap<AgentId, Portfolio>,
+
+    // every balance change below goes through the ledger
+    ledger: Ledger,
+    accounts: HashMap<AgentId, Account>,
     order_map: HashMap<u64, AgentId>,
+
+    // inputs for a replay, only kept when asked for
+    event_log: RefCell<Option<EventLog>>,
+
+    // master seed every random stream of the run is derived from
+    seed: u64,
+    fundamental: Option<Fundamental>,
 }
 
 impl<CommodityType> Market<CommodityType> {
+    pub fn new(info: MarketInfo<CommodityType>) -> Market<CommodityType> {
+        Self::with_engine(info, OrderBook::default())
+    }
+}
+
+impl<CommodityType, Engine: MatchingEngine> Market<CommodityType, Engine> {
     pub type AgentRefType = AgentRefType<CommodityType>;
     pub type AgentType = AgentRefType<CommodityType>;
 
-    pub fn new(info: MarketInfo<CommodityType>) -> Market<CommodityType> {
+    // the same market over another way of matching orders
+    pub fn with_engine(info: MarketInfo<CommodityType>, engine: Engine) -> Self {
         Self {
-            book: Default::default(),
+            book: engine,
             info,
             id: Default::default(),
             market_account: Default::default(),
+            fees: Default::default(),
+            credit: Default::default(),
+            lending: Default::default(),
+            lendable: Default::default(),
+            located: Default::default(),
+            budgets: Default::default(),
+            bankruptcy: Default::default(),
+            distress: Default::default(),
+            retired: Default::default(),
+            settlement: Default::default(),
+            obligations: Default::default(),
+            portfolios: Default::default(),
+            ledger: Default::default(),
             accounts: Default::default(),
             order_map: Default::default(),
+            event_log: Default::default(),
+            seed: Default::default(),
+            fundamental: Default::default(),
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        if let Some(fundamental) = self.fundamental.as_mut() {
+            fundamental.reseed(SimRng::stream(seed, FUNDAMENTAL_STREAM));
+        }
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The random stream handed to an agent at setup. It depends only on the seed and
+    /// the agent id, so agents don't disturb each other's draws.
+    pub fn agent_rng(&self, agent_id: AgentId) -> SimRng {
+        SimRng::stream(self.seed, agent_id.0)
+    }
+
+    // the value moves on with every `start_step`, on a stream of the market seed
+    pub fn with_fundamental(mut self, mut fundamental: Fundamental) -> Self {
+        fundamental.reseed(SimRng::stream(self.seed, FUNDAMENTAL_STREAM));
+        self.fundamental = Some(fundamental);
+        self
+    }
+
+    pub fn fundamental(&self) -> Option<Amount> {
+        self.fundamental.as_ref().map(Fundamental::value)
+    }
+
+    // the tax collector, if any, has to be registered already
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        assert!(fees.is_valid(), "{:?} can leave proceeds negative", fees);
+        assert!(
+            fees.tax_account
+                .is_none_or(|collector_id| self.accounts.contains_key(&collector_id)),
+            "tax collector of {:?} has no account",
+            fees
+        );
+        self.fees = fees;
+        self
+    }
+
+    pub fn fees(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    pub fn with_credit(mut self, credit: CreditPolicy) -> Self {
+        assert!(
+            credit.is_valid(),
+            "{:?} is not a valid credit policy",
+            credit
+        );
+        self.credit = credit;
+        self
+    }
+
+    pub fn credit(&self) -> &CreditPolicy {
+        &self.credit
+    }
+
+    pub fn set_credit_limit(&mut self, agent_id: AgentId, limit: Amount) -> bool {
+        self.log(Event::SetCreditLimit(agent_id, limit));
+        self.accounts
+            .get_mut(&agent_id)
+            .map(|acc| acc.credit_limit = limit)
+            .is_some()
+    }
+
+    pub fn with_lending(mut self, lending: LendingPolicy) -> Self {
+        assert!(
+            lending.is_valid(),
+            "{:?} is not a valid lending policy",
+            lending
+        );
+        self.lending = Some(lending);
+        self
+    }
+
+    pub fn lending(&self) -> Option<&LendingPolicy> {
+        self.lending.as_ref()
+    }
+
+    // hands commodity to the market account to be lent out
+    pub fn fund_lending_pool(&mut self, units: Quantity) {
+        self.log(Event::FundLendingPool(units));
+        self.post(
+            PostingKind::Injection,
+            Holder::External.commodity(),
+            Holder::Market.commodity(),
+            units.as_int,
+        );
+        self.lendable += units;
+    }
+
+    pub fn short_interest(&self) -> ShortInterest {
+        ShortInterest {
+            borrowed: self
+                .accounts
+                .values()
+                .map(|acc| acc.borrowed_commodity)
+                .sum(),
+            lendable: self.lendable,
+        }
+    }
+
+    pub fn with_bankruptcy(mut self, bankruptcy: BankruptcyPolicy) -> Self {
+        assert!(
+            bankruptcy.is_valid(),
+            "{:?} needs a step below a threshold of at most zero",
+            bankruptcy
+        );
+        self.bankruptcy = Some(bankruptcy);
+        self
+    }
+
+    pub fn bankruptcy(&self) -> Option<&BankruptcyPolicy> {
+        self.bankruptcy.as_ref()
+    }
+
+    pub fn with_settlement(mut self, settlement: SettlementPolicy) -> Self {
+        assert!(
+            settlement.is_valid(),
+            "{:?} is not a valid settlement policy",
+            settlement
+        );
+        self.settlement = settlement;
+        self
+    }
+
+    pub fn settlement(&self) -> &SettlementPolicy {
+        &self.settlement
+    }
+
+    pub fn pending_obligations(&self) -> &[Obligation] {
+        &self.obligations
+    }
+
+    /// Settles the obligations due at the current step. Askers deliver what they hold,
+    /// short sales borrow the rest from the lending pool, and undelivered units are
+    /// penalised and bought in as the settlement policy says. To be called once per
+    /// step before orders are submitted, returns the failures keyed by the asker.
+    pub fn settle_obligations(&mut self) -> Vec<(AgentId, DeliveryFailure)> {
+        self.log(Event::SettleObligations);
+        let step = self.ledger.step();
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.obligations)
+            .into_iter()
+            .partition(|obligation| obligation.due <= step);
+        self.obligations = pending;
+
+        due.into_iter()
+            .filter_map(|obligation| {
+                self.settle_obligation(obligation)
+                    .map(|failure| (obligation.asker, failure))
+            })
+            .collect()
+    }
+
+    // all money in the market including posted collateral, constant under trading
+    pub fn total_money(&self) -> Amount {
+        [self.market_account]
+            .iter()
+            .chain(self.accounts.values())
+            .fold(Amount::new(), |total, acc| {
+                total + acc.money + acc.collateral
+            })
+    }
+
+    // all commodity in the market including the lending pool, constant under trading
+    pub fn total_commodity(&self) -> Quantity {
+        [self.market_account]
+            .iter()
+            .chain(self.accounts.values())
+            .map(|acc| acc.commodity)
+            .sum()
+    }
+
+    pub fn market_account(&self) -> Account {
+        self.market_account
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    // postings made from now on are booked at `step`
+    pub fn start_step(&mut self, step: u64) {
+        if let Some(log) = self.event_log.borrow_mut().as_mut() {
+            log.start_step(step, self.all_accounts());
+        }
+        self.ledger.set_step(step);
+        if let Some(fundamental) = self.fundamental.as_mut() {
+            fundamental.advance(step);
+        }
+    }
+
+    // negative amounts withdraw, refused beyond the free money
+    pub fn inject_money(&mut self, agent_id: AgentId, money: Amount) -> bool {
+        self.log(Event::InjectMoney(agent_id, money));
+        let covered = (self.accounts.get(&agent_id)).is_some_and(|acc| {
+            money.as_int >= 0
+                || (acc.free_money().checked_add(money)).is_some_and(|left| left.as_int >= 0)
+        });
+        if !covered {
+            return false;
+        }
+        self.inject(agent_id, Book::Money, money.as_int)
+    }
+
+    // negative amounts withdraw, refused beyond the free commodity
+    pub fn inject_commodity(&mut self, agent_id: AgentId, units: Quantity) -> bool {
+        self.log(Event::InjectCommodity(agent_id, units));
+        let covered = (self.accounts.get(&agent_id)).is_some_and(|acc| {
+            units.as_int >= 0
+                || (acc.free_commodity().checked_add(units)).is_some_and(|left| left.as_int >= 0)
+        });
+        if !covered {
+            return false;
         }
+        self.inject(agent_id, Book::Commodity, units.as_int)
+    }
+
+    // makes `units` for the agent and charges it `cost`,
+    // refused when it can't pay for them
+    pub fn produce(&mut self, agent_id: AgentId, units: Quantity, cost: Amount) -> bool {
+        self.log(Event::Produce(agent_id, units, cost));
+        let affordable = (self.accounts.get(&agent_id))
+            .is_some_and(|acc| cost.as_int >= 0 && cost <= acc.purchasing_power());
+        if !affordable || !units.is_positive() {
+            return false;
+        }
+
+        let holder = Holder::Agent(agent_id);
+        let kind = PostingKind::Production;
+        self.post(
+            kind,
+            Holder::External.commodity(),
+            holder.commodity(),
+            units.as_int,
+        );
+        self.post(kind, holder.money(), Holder::External.money(), cost.as_int);
+        self.settle_credit(&agent_id);
+        true
+    }
+
+    // records every call that changes the market from now on, see `replay`
+    pub fn with_event_log(self) -> Self {
+        self.event_log.replace(Some(EventLog::default()));
+        self
+    }
+
+    // the log up to now, with the current step closed at the present balances
+    pub fn event_log(&self) -> Option<EventLog> {
+        let mut log = self.event_log.borrow().clone()?;
+        log.close(self.all_accounts());
+        Some(log)
+    }
+
+    // the market account followed by the agents in id order
+    pub fn all_accounts(&self) -> Vec<(Holder, Account)> {
+        [(Holder::Market, self.market_account)]
+            .into_iter()
+            .chain(
+                self.sorted_ids()
+                    .into_iter()
+                    .map(|id| (Holder::Agent(id), self.accounts[&id])),
+            )
+            .collect()
+    }
+
+    /// Checks every account against the balances reconstructed from the ledger, retired
+    /// agents have to be left with nothing. Returns the holders that disagree together
+    /// with their ledger balances and the expected ones.
+    pub fn audit(&self) -> Vec<(Holder, Balances, Balances)> {
+        let agents = self
+            .accounts
+            .iter()
+            .map(|(id, acc)| (Holder::Agent(*id), Balances::from(acc)));
+        let retired = self
+            .retired
+            .iter()
+            .map(|id| (Holder::Agent(*id), Balances::default()));
+
+        // the market is owed whatever the agents owe
+        let market = Balances {
+            dept: -self.accounts.values().map(|acc| acc.dept).sum::<Amount>(),
+            borrowed_commodity: -self.short_interest().borrowed,
+            ..Balances::from(&self.market_account)
+        };
+
+        let mut mismatches: Vec<_> = std::iter::once((Holder::Market, market))
+            .chain(agents)
+            .chain(retired)
+            .map(|(holder, expected)| (holder, self.ledger.balances(holder), expected))
+            .filter(|(_, booked, expected)| booked != expected)
+            .collect();
+        mismatches.sort_by_key(|(holder, _, _)| *holder);
+        mismatches
+    }
+
+    // resting orders by price level, as the engine sees them
+    pub fn book_snapshot(&self) -> BookSnapshot {
+        self.book.snapshot()
     }
 
     pub fn all_orders(&self) -> Vec<(AgentId, Order)> {
@@ -137,6 +604,7 @@ impl<CommodityType> Market<CommodityType> {
         submitter: &AgentId,
         submissions: &[OrderData],
     ) -> Vec<Option<Order>> {
+        self.log(Event::CreateOrders(*submitter, submissions.to_vec()));
         submissions
             .iter()
             .map(|data| {
@@ -147,15 +615,40 @@ impl<CommodityType> Market<CommodityType> {
             .collect()
     }
 
+    // the data of a spec the market can take: one for its own instrument that lasts the
+    // step, as the book is cleared after every one
+    pub fn honoured(&self, spec: &OrderSpec) -> Option<OrderData> {
+        let instrument = (spec.instrument.as_ref()).is_none_or(|name| *name == self.info.name);
+        (instrument && spec.time_in_force == TimeInForce::Day).then_some(spec.data)
+    }
+
     pub fn submit_order(&mut self, submitter: &AgentId, order: Order) -> Option<Order> {
-        let reserved = self
-            .accounts
-            .get_mut(submitter)
-            .unwrap()
-            .reserve_order(order);
+        self.log(Event::SubmitOrder(*submitter, order));
+        let locate = match self.lending {
+            Some(lending) => {
+                let locate = lending.locate_need(&self.accounts[submitter], &order);
+                let available = self.short_interest().available() - self.located;
+
+                if locate.units.is_positive() && locate.units > available {
+                    return Some(order);
+                }
+                locate
+            }
+            None => Default::default(),
+        };
+
+        let account = self.accounts.get_mut(submitter).unwrap();
+        // a market bid can't be priced up front, its fills are capped at what is left
+        let budget = (order.side == OrderSide::Bid && order.price.is_none())
+            .then(|| account.purchasing_power() - account.reserved_money);
+        let reserved = account.reserve_order_located(order, &self.fees, locate);
 
         if reserved {
-            let order_id = Into::<Order>::into(order).id;
+            self.located += locate.units;
+            let order_id = order.id;
+            if let Some(budget) = budget {
+                self.budgets.insert(order_id, budget);
+            }
             self.order_map.insert(order_id, *submitter);
             self.book.add_order(order);
             None
@@ -164,10 +657,30 @@ impl<CommodityType> Market<CommodityType> {
         }
     }
 
+    // balances of the account are booked as injections
     pub fn register_with_acc(&mut self, account: Account) -> AgentId {
+        self.log(Event::Register(account));
         let id = AgentId::new(*self.id.borrow());
         *self.id.borrow_mut() += 1;
-        self.accounts.insert(id, account);
+        self.accounts.insert(
+            id,
+            Account {
+                credit_limit: account.credit_limit,
+                ..Default::default()
+            },
+        );
+
+        [
+            (Book::Money, account.money.as_int),
+            (Book::Collateral, account.collateral.as_int),
+            (Book::Debt, -account.dept.as_int),
+            (Book::Commodity, account.commodity.as_int),
+            (Book::Borrowed, -account.borrowed_commodity.as_int),
+        ]
+        .into_iter()
+        .for_each(|(book, amount)| {
+            self.inject(id, book, amount);
+        });
         id
     }
 
@@ -179,6 +692,168 @@ impl<CommodityType> Market<CommodityType> {
         self.register_with_acc(Account::default())
     }
 
+    /// Removes the agent from the market: its orders are cancelled, borrowed commodity
+    /// is returned from its holdings or paid for with the collateral, debt is repaid
+    /// from its money and written off beyond that, and whatever is left goes to `settlement`.
+    /// Returns the account as it was when the agent left.
+    pub fn deregister(&mut self, agent_id: AgentId, settlement: Settlement) -> Option<Account> {
+        self.log(Event::Deregister(agent_id, settlement));
+        self.retire(agent_id, settlement)
+    }
+
+    fn retire(&mut self, agent_id: AgentId, settlement: Settlement) -> Option<Account> {
+        if let Settlement::ToAgent(heir_id) = settlement
+            && (heir_id == agent_id || !self.accounts.contains_key(&heir_id))
+        {
+            return None;
+        }
+
+        if !self.accounts.contains_key(&agent_id) {
+            return None;
+        }
+
+        let orders: Vec<_> = self
+            .order_map
+            .iter()
+            .filter(|(_, owner)| **owner == agent_id)
+            .map(|(order_id, _)| *order_id)
+            .collect();
+        orders.into_iter().for_each(|order_id| {
+            self.order_map.remove(&order_id);
+            self.book.cancel_order(order_id);
+        });
+
+        // unsettled fills of the agent are called off
+        let (cancelled, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.obligations)
+            .into_iter()
+            .partition(|obligation| obligation.bidder == agent_id || obligation.asker == agent_id);
+        self.obligations = pending;
+        cancelled
+            .iter()
+            .for_each(|obligation| self.unlock(obligation));
+
+        let acc = self.accounts.get_mut(&agent_id).unwrap();
+        acc.reserved_money = Amount::new();
+        acc.reserved_commodity = Quantity::ZERO;
+        let last = *acc;
+        let holder = Holder::Agent(agent_id);
+        let kind = PostingKind::Deregistration;
+
+        // unreturned units are lost to the lender, who keeps the collateral instead
+        let returned = last
+            .commodity
+            .min(last.borrowed_commodity)
+            .max(Quantity::ZERO);
+        self.return_borrowed(agent_id, returned);
+        let acc = self.accounts[&agent_id];
+        self.lendable -= acc.borrowed_commodity;
+        self.post(
+            kind,
+            holder.collateral(),
+            Holder::Market.money(),
+            acc.collateral.as_int,
+        );
+        self.post(
+            kind,
+            Holder::Market.borrowed(),
+            holder.borrowed(),
+            acc.borrowed_commodity.as_int,
+        );
+
+        self.settle_credit(&agent_id);
+        let acc = self.accounts[&agent_id];
+        self.post(kind, Holder::Market.debt(), holder.debt(), acc.dept.as_int);
+
+        let heir = match settlement {
+            Settlement::ToMarket => Holder::Market,
+            Settlement::ToAgent(heir_id) => Holder::Agent(heir_id),
+        };
+        self.post(kind, holder.money(), heir.money(), acc.money.as_int);
+        self.post(
+            kind,
+            holder.commodity(),
+            heir.commodity(),
+            acc.commodity.as_int,
+        );
+
+        self.accounts.remove(&agent_id);
+        self.retired.insert(agent_id);
+        self.distress.remove(&agent_id);
+
+        Some(last)
+    }
+
+    pub fn is_retired(&self, agent_id: &AgentId) -> bool {
+        self.retired.contains(agent_id)
+    }
+
+    pub fn retired(&self) -> &HashSet<AgentId> {
+        &self.retired
+    }
+
+    // deregisters agents that stayed below the bankruptcy threshold for long enough,
+    // to be called once per step
+    pub fn check_bankruptcies(&mut self, reference_price: Amount) -> Vec<(AgentId, Account)> {
+        self.log(Event::CheckBankruptcies(reference_price));
+        let Some(bankruptcy) = self.bankruptcy else {
+            return vec![];
+        };
+
+        self.sorted_ids()
+            .into_iter()
+            .filter(|id| {
+                let acc = &self.accounts[id];
+                let distress = self.distress.entry(*id).or_default();
+
+                if acc.equity(reference_price) < bankruptcy.threshold {
+                    *distress += 1;
+                } else {
+                    *distress = 0;
+                }
+
+                *distress >= bankruptcy.steps
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|id| self.retire(id, bankruptcy.settlement).map(|acc| (id, acc)))
+            .collect()
+    }
+
+    pub fn portfolio(&self, agent_id: AgentId) -> Option<&Portfolio> {
+        self.portfolios.get(&agent_id)
+    }
+
+    pub fn portfolio_stats(
+        &self,
+        agent_id: AgentId,
+        reference_price: Amount,
+    ) -> Option<PortfolioStats> {
+        self.portfolio(agent_id)
+            .map(|portfolio| portfolio.stats(reference_price))
+    }
+
+    // stats of every active agent, ordered by id
+    pub fn all_portfolio_stats(&self, reference_price: Amount) -> Vec<(AgentId, PortfolioStats)> {
+        self.sorted_ids()
+            .into_iter()
+            .filter_map(|id| {
+                self.portfolio_stats(id, reference_price)
+                    .map(|stats| (id, stats))
+            })
+            .collect()
+    }
+
+    // records the equity of every active agent, to be called once per step
+    pub fn mark_portfolios(&mut self, reference_price: Amount) {
+        self.log(Event::MarkPortfolios(reference_price));
+        self.accounts.iter().for_each(|(id, acc)| {
+            self.portfolios
+                .entry(*id)
+                .or_default()
+                .mark(acc.equity(reference_price));
+        });
+    }
+
     pub fn owner(&self, order: &LimitOrder) -> Option<AgentId> {
         let order_id = Into::<Order>::into(*order).id;
         self.order_map.get(&order_id).cloned()
@@ -189,27 +864,155 @@ impl<CommodityType> Market<CommodityType> {
     }
 
     pub fn clear_reserves_and_orders(&mut self) {
+        self.log(Event::ClearReservesAndOrders);
+        self.located = Quantity::ZERO;
+        self.budgets.clear();
         self.order_map.clear();
         self.clear_reservations();
         self.book.clear_orders();
     }
 
+    // charges interest on every debt, to be called once per step
+    pub fn accrue_interest(&mut self) {
+        self.log(Event::AccrueInterest);
+        for id in self.sorted_ids() {
+            let interest = self.credit.interest(&self.accounts[&id]);
+            self.post(
+                PostingKind::Interest,
+                Holder::Agent(id).debt(),
+                Holder::Market.debt(),
+                interest.as_int,
+            );
+        }
+    }
+
+    // warns debtors below the margin call ratio and places liquidation orders
+    // for those below the maintenance ratio, to be called before orders are submitted
+    pub fn check_margins(&mut self, reference_price: Amount) -> Vec<(AgentId, MarginCall)> {
+        self.log(Event::CheckMargins(reference_price));
+        let mut debtors: Vec<_> = self
+            .accounts
+            .iter()
+            .filter_map(|(id, acc)| {
+                self.credit
+                    .margin_call(acc, reference_price)
+                    .map(|call| (*id, call))
+            })
+            .collect();
+        debtors.sort_by_key(|(id, _)| *id);
+
+        debtors
+            .into_iter()
+            .map(|(id, mut call)| {
+                if call.ratio_bps < self.credit.maintenance_bps {
+                    call.liquidation = self.liquidate(&id, reference_price);
+                }
+                (id, call)
+            })
+            .collect()
+    }
+
+    // returns covered shorts to the pool and charges the borrow fee, to be called once per step
+    pub fn settle_lending(&mut self, reference_price: Amount) {
+        self.log(Event::SettleLending(reference_price));
+        let Some(lending) = self.lending else {
+            return;
+        };
+
+        for id in self.sorted_ids() {
+            let acc = self.accounts[&id];
+            let returned = acc
+                .commodity
+                .min(acc.borrowed_commodity)
+                .max(Quantity::ZERO);
+            self.return_borrowed(id, returned);
+
+            let fee = lending.borrow_fee(&self.accounts[&id], reference_price);
+            self.post(
+                PostingKind::Lending,
+                Holder::Agent(id).money(),
+                Holder::Market.money(),
+                fee.as_int,
+            );
+            self.settle_credit(&id);
+        }
+    }
+
+    // the lender withdraws units from the pool, borrowers return what they hold
+    // and the rest is bought in with market bids paid from the released collateral
+    pub fn recall(&mut self, units: Quantity) -> Vec<(AgentId, Recall)> {
+        self.log(Event::Recall(units));
+        self.lendable = (self.lendable - units).max(Quantity::ZERO);
+        let mut excess = self.short_interest().borrowed - self.lendable;
+
+        let mut recalls = Vec::new();
+        for id in self.sorted_ids() {
+            if !excess.is_positive() {
+                break;
+            }
+
+            let acc = self.accounts[&id];
+            let owed = acc.borrowed_commodity.min(excess);
+            if !owed.is_positive() {
+                continue;
+            }
+            excess -= owed;
+
+            let returned = self.return_borrowed(id, acc.commodity.min(owed).max(Quantity::ZERO));
+
+            let missing = owed - returned;
+            let buy_in = if missing.is_positive() {
+                let holder = Holder::Agent(id);
+                let collateral = self.accounts[&id].collateral;
+                self.post(
+                    PostingKind::Lending,
+                    holder.collateral(),
+                    holder.money(),
+                    collateral.as_int,
+                );
+
+                self.book
+                    .new_order_checked(OrderData {
+                        side: OrderSide::Bid,
+                        price: None,
+                        size: missing,
+                    })
+                    .inspect(|order| {
+                        self.order_map.insert(order.id, id);
+                        self.book.add_order(*order);
+                    })
+            } else {
+                None
+            };
+
+            recalls.push((id, Recall { returned, buy_in }));
+        }
+
+        recalls
+    }
+
     pub fn agents_submit_orders(
         &mut self,
         agents: &[(AgentId, Self::AgentRefType)],
         history: &History,
     ) -> Vec<(AgentId, Order)> {
-        let orders: Vec<(Option<Order>, AgentId)> = agents
-            .iter()
-            .filter_map(|(id, agent)| self.account(*id).map(|account| (id, agent, account)))
-            .flat_map(|(id, agent, account)| {
-                let data = (*agent.borrow_mut()).produce_orders(&account, &self.info, history);
+        let mut orders: Vec<(Option<Order>, AgentId)> = Vec::new();
+        for (id, agent) in agents {
+            let Some(account) = self.account(*id) else {
+                continue;
+            };
+            let mut agent = agent.borrow_mut();
 
-                self.create_orders(id, data.as_slice())
-                    .into_iter()
-                    .zip(std::iter::repeat(*id))
-            })
-            .collect();
+            // what it makes is there before it quotes
+            let account = match agent.production(&account, &self.info, history) {
+                Some((units, cost)) if self.produce(*id, units, cost) => self.account(*id).unwrap(),
+                _ => account,
+            };
+            let data = agent.produce_orders(&account, &self.info, history);
+
+            let created = self.create_orders(id, data.as_slice());
+            orders.extend(created.into_iter().zip(std::iter::repeat(*id)));
+        }
 
         orders
             .into_iter()
@@ -220,62 +1023,194 @@ impl<CommodityType> Market<CommodityType> {
             .collect()
     }
 
+    // trims the fills of market bids to what their bidders can pay, charges included,
+    // dropping the rest
+    fn within_budget(&mut self, transactions: Vec<Transaction>) -> Vec<Transaction> {
+        transactions
+            .into_iter()
+            .filter_map(|trns| {
+                let Some(budget) = self.budgets.get_mut(&trns.bid_id) else {
+                    return Some(trns);
+                };
+                // fixed charges are per fill, so the part is charged afresh
+                let part = |size: Quantity| self.fees.apply(trns.partial(size));
+
+                // the largest part of the fill the budget covers
+                let (mut low, mut high) = (0, trns.size.as_int);
+                while low < high {
+                    let mid = high - (high - low) / 2;
+                    if part(Quantity::new(mid)).payment() <= *budget {
+                        low = mid;
+                    } else {
+                        high = mid - 1;
+                    }
+                }
+
+                let trns = part(Quantity::new(low));
+                if !trns.size.is_positive() {
+                    return None;
+                }
+                *budget -= trns.payment();
+                Some(trns)
+            })
+            .collect()
+    }
+
     pub fn process_submitted_orders(
         &mut self,
         prev_market_price: Option<Amount>,
     ) -> Vec<Transaction> {
+        self.log(Event::ProcessSubmittedOrders(prev_market_price));
         // Assumes clean history
 
-        let primary_market_transactions = self.book.match_all_market(None);
+        let matched = self.fees.apply_all(self.book.match_step(prev_market_price));
+        let transactions = self.within_budget(matched);
 
-        primary_market_transactions
+        transactions
             .iter()
             .for_each(|trns| self.fulfill_transaction(trns));
 
-        // determine new market price
-        let market_price = if primary_market_transactions.is_empty() {
-            None
-        } else {
-            Some(
-                primary_market_transactions
-                    .iter()
-                    .map(|tr| (tr.ask_gain + tr.bid_loss).as_int / 2)
-                    .sum(),
-            )
+        // record all the history for next step
+        if let Some(log) = self.event_log.get_mut() {
+            log.record_transactions(&transactions);
         }
-        .map(|as_int| Amount { as_int });
+        transactions
+    }
+}
 
-        //if there are market transactions left, match and fullfil them with either
-        // * current market price
-        // * previous market price
-        let secondary_market_transactions = self
-            .book
-            .match_all_market(market_price.or(prev_market_price));
+impl<CommodityType, Engine: MatchingEngine> Market<CommodityType, Engine> {
+    // forced sale bypasses the money checks, charges are drawn from the credit line
+    fn liquidate(&mut self, agent_id: &AgentId, reference_price: Amount) -> Option<Order> {
+        let acc = self.accounts.get_mut(agent_id)?;
+        let data = self.credit.liquidation_order(acc, reference_price);
+        let order = self.book.new_order_checked(data)?;
 
-        secondary_market_transactions
-            .iter()
-            .for_each(|trns| self.fulfill_transaction(trns));
+        acc.reserved_commodity += order.size;
+        self.order_map.insert(order.id, *agent_id);
+        self.book.add_order(order);
 
-        //if there are limit transactions left, match and fullfil as much as possible
+        Some(order)
+    }
 
-        let limit_transactions = self.book.match_all_limit();
+    fn log(&self, event: Event) {
+        if let Some(log) = self.event_log.borrow_mut().as_mut() {
+            log.record(event);
+        }
+    }
 
-        limit_transactions
-            .iter()
-            .for_each(|trns| self.fulfill_transaction(trns));
+    fn sorted_ids(&self) -> Vec<AgentId> {
+        let mut ids: Vec<_> = self.accounts.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
 
-        // record all the history for next step
+    // books a balance change on both legs and applies it to the accounts
+    fn post(&mut self, kind: PostingKind, from: Leg, to: Leg, amount: i64) {
+        if let Some(posting) = self.ledger.record(kind, from, to, amount) {
+            self.apply(posting.from, -posting.amount);
+            self.apply(posting.to, posting.amount);
+        }
+    }
 
-        [
-            primary_market_transactions,
-            secondary_market_transactions,
-            limit_transactions,
-        ]
-        .concat()
+    fn apply(&mut self, leg: Leg, amount: i64) {
+        let acc = match leg.holder {
+            Holder::External => return,
+            // what the market is owed is only kept in the ledger
+            Holder::Market if matches!(leg.book, Book::Debt | Book::Borrowed) => return,
+            Holder::Market => &mut self.market_account,
+            Holder::Agent(id) => {
+                let Some(acc) = self.accounts.get_mut(&id) else {
+                    panic!("{:?} has no account", leg.holder);
+                };
+                acc
+            }
+        };
+
+        match leg.book {
+            Book::Money => acc.money.as_int += amount,
+            Book::Collateral => acc.collateral.as_int += amount,
+            Book::Debt => acc.dept.as_int -= amount,
+            Book::Commodity => acc.commodity.as_int += amount,
+            Book::Borrowed => acc.borrowed_commodity.as_int -= amount,
+        }
+    }
+
+    fn inject(&mut self, agent_id: AgentId, book: Book, amount: i64) -> bool {
+        if !self.accounts.contains_key(&agent_id) {
+            return false;
+        }
+
+        let holder = Holder::Agent(agent_id);
+        self.post(
+            PostingKind::Injection,
+            Leg {
+                holder: Holder::External,
+                book,
+            },
+            Leg { holder, book },
+            amount,
+        );
+        true
+    }
+
+    // hands borrowed units back and releases the matching share of collateral
+    fn return_borrowed(&mut self, agent_id: AgentId, units: Quantity) -> Quantity {
+        if !units.is_positive() {
+            return Quantity::ZERO;
+        }
+
+        let acc = &self.accounts[&agent_id];
+        let released = acc
+            .collateral
+            .pro_rata(units, acc.borrowed_commodity)
+            .unwrap_or(acc.collateral)
+            .as_int;
+        let holder = Holder::Agent(agent_id);
+
+        self.post(
+            PostingKind::Lending,
+            holder.commodity(),
+            Holder::Market.commodity(),
+            units.as_int,
+        );
+        self.post(
+            PostingKind::Lending,
+            Holder::Market.borrowed(),
+            holder.borrowed(),
+            units.as_int,
+        );
+        self.post(
+            PostingKind::Lending,
+            holder.collateral(),
+            holder.money(),
+            released,
+        );
+
+        units
+    }
+
+    // draws on the credit line to cover negative money, repays debt from positive money
+    fn settle_credit(&mut self, agent_id: &AgentId) {
+        let Some(acc) = self.accounts.get(agent_id) else {
+            return;
+        };
+
+        let drawn = acc.credit_draw().as_int;
+        let holder = Holder::Agent(*agent_id);
+        self.post(
+            PostingKind::Credit,
+            Holder::Market.money(),
+            holder.money(),
+            drawn,
+        );
+        self.post(
+            PostingKind::Credit,
+            holder.debt(),
+            Holder::Market.debt(),
+            drawn,
+        );
     }
-}
 
-impl<CommodityType> Market<CommodityType> {
     fn clear_reservations(&mut self) {
         self.accounts.iter_mut().for_each(|(_, acc_mut)| {
             acc_mut.reserved_commodity = Default::default();
@@ -284,27 +1219,174 @@ impl<CommodityType> Market<CommodityType> {
     }
 
     fn fulfill_transaction(&mut self, trns: &Transaction) {
-        let Some(bidder_id) = self.order_map.get(&trns.bid_id) else {
+        let Some(&bidder_id) = self.order_map.get(&trns.bid_id) else {
             panic!("{:?} has no bidder", trns);
         };
-        let Some(asker_id) = self.order_map.get(&trns.ask_id) else {
+        let Some(&asker_id) = self.order_map.get(&trns.ask_id) else {
             panic!("{:?} has no asker", trns);
         };
 
-        let Some(bidder_acc) = self.accounts.get_mut(bidder_id) else {
+        if self.settlement.lag == 0 {
+            self.deliver(bidder_id, asker_id, trns);
+            return;
+        }
+
+        let obligation = Obligation {
+            due: self.ledger.step() + self.settlement.lag,
+            bidder: bidder_id,
+            asker: asker_id,
+            transaction: *trns,
+        };
+        let Some(bidder_acc) = self.accounts.get_mut(&bidder_id) else {
             panic!("bidder of {:?} has no account", trns);
         };
+        bidder_acc.locked_money += obligation.payment();
+        let Some(asker_acc) = self.accounts.get_mut(&asker_id) else {
+            panic!("asker of {:?} has no account", trns);
+        };
+        asker_acc.locked_commodity += trns.size;
+        self.obligations.push(obligation);
+    }
+
+    fn unlock(&mut self, obligation: &Obligation) {
+        if let Some(bidder_acc) = self.accounts.get_mut(&obligation.bidder) {
+            bidder_acc.locked_money -= obligation.payment();
+        }
+        if let Some(asker_acc) = self.accounts.get_mut(&obligation.asker) {
+            asker_acc.locked_commodity -= obligation.transaction.size;
+        }
+    }
+
+    fn settle_obligation(&mut self, obligation: Obligation) -> Option<DeliveryFailure> {
+        self.unlock(&obligation);
+
+        let trns = obligation.transaction;
+        let (bidder_id, asker_id) = (obligation.bidder, obligation.asker);
+
+        // short sales can borrow what the pool still lends
+        let borrowable = match self.lending {
+            Some(_) => self.short_interest().available(),
+            None => Quantity::ZERO,
+        };
+        let held = self.accounts[&asker_id].commodity.max(Quantity::ZERO);
+        let delivered = trns.size.min(held + borrowable);
+        let missing = trns.size - delivered;
+
+        if missing == Quantity::ZERO {
+            self.deliver(bidder_id, asker_id, &trns);
+            return None;
+        }
+        if delivered.is_positive() {
+            self.deliver(bidder_id, asker_id, &obligation.partial(delivered));
+        }
+
+        let penalty = self.settlement.penalty(&trns, missing);
+        self.post(
+            PostingKind::Penalty,
+            Holder::Agent(asker_id).money(),
+            Holder::Agent(bidder_id).money(),
+            penalty.as_int,
+        );
+        self.settle_credit(&asker_id);
+        self.settle_credit(&bidder_id);
 
-        self.market_account.money += trns.diff;
+        let buy_in = if self.settlement.buy_in {
+            self.book
+                .new_order_checked(OrderData {
+                    side: OrderSide::Bid,
+                    price: None,
+                    size: missing,
+                })
+                .inspect(|order| {
+                    self.order_map.insert(order.id, bidder_id);
+                    self.book.add_order(*order);
+                })
+        } else {
+            None
+        };
 
-        bidder_acc.commodity += trns.size;
-        bidder_acc.money -= trns.bid_loss;
+        Some(DeliveryFailure {
+            obligation,
+            delivered,
+            penalty,
+            buy_in,
+        })
+    }
 
-        let Some(asker_acc) = self.accounts.get_mut(asker_id) else {
+    fn deliver(&mut self, bidder_id: AgentId, asker_id: AgentId, trns: &Transaction) {
+        if !self.accounts.contains_key(&bidder_id) {
+            panic!("bidder of {:?} has no account", trns);
+        }
+        let Some(asker_acc) = self.accounts.get(&asker_id) else {
             panic!("asker of {:?} has no account", trns);
         };
+        // the market collects the tax once the collector has left
+        let collector = match self.fees.tax_account {
+            Some(collector_id) if self.accounts.contains_key(&collector_id) => {
+                Holder::Agent(collector_id)
+            }
+            _ => Holder::Market,
+        };
+
+        let bidder = Holder::Agent(bidder_id);
+        let asker = Holder::Agent(asker_id);
+        let market = Holder::Market;
 
-        asker_acc.commodity -= trns.size;
-        asker_acc.money += trns.ask_gain;
+        // short sales borrow what the asker doesn't hold
+        let shortfall = (trns.size - asker_acc.commodity).max(Quantity::ZERO);
+        if shortfall.is_positive() {
+            let Some(lending) = self.lending else {
+                panic!("asker of {:?} sells commodity it doesn't have", trns);
+            };
+
+            let value = trns
+                .ask_gain
+                .pro_rata(shortfall, trns.size)
+                .unwrap_or(trns.ask_gain);
+            let collateral = bps_of(value, lending.collateral_bps).as_int;
+
+            let kind = PostingKind::Lending;
+            self.post(
+                kind,
+                market.commodity(),
+                asker.commodity(),
+                shortfall.as_int,
+            );
+            self.post(kind, asker.borrowed(), market.borrowed(), shortfall.as_int);
+            self.post(kind, asker.money(), asker.collateral(), collateral);
+        }
+
+        let kind = PostingKind::Trade;
+        self.post(
+            kind,
+            asker.commodity(),
+            bidder.commodity(),
+            trns.size.as_int,
+        );
+        self.post(kind, bidder.money(), asker.money(), trns.ask_gain.as_int);
+        self.post(kind, bidder.money(), market.money(), trns.diff.as_int);
+
+        let kind = PostingKind::Fee;
+        self.post(kind, bidder.money(), market.money(), trns.bid_fee.as_int);
+        self.post(kind, asker.money(), market.money(), trns.ask_fee.as_int);
+
+        let kind = PostingKind::Tax;
+        self.post(kind, bidder.money(), collector.money(), trns.bid_tax.as_int);
+        self.post(kind, asker.money(), collector.money(), trns.ask_tax.as_int);
+
+        self.portfolios
+            .entry(bidder_id)
+            .or_default()
+            .fill(OrderSide::Bid, trns);
+        self.portfolios
+            .entry(asker_id)
+            .or_default()
+            .fill(OrderSide::Ask, trns);
+
+        self.settle_credit(&bidder_id);
+        self.settle_credit(&asker_id);
+        if let Some(collector_id) = self.fees.tax_account {
+            self.settle_credit(&collector_id);
+        }
     }
 }
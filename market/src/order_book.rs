@@ -5,10 +5,12 @@ use super::{
         limit::{AskLimitOrder, BidLimitOrder, LimitOrder},
         market::{AskMarketOrder, BidMarketOrder, MarketOrder},
     },
+    quantity::Quantity,
 };
 use std::{cell::RefCell, cmp::Ordering, collections::BinaryHeap, fmt::Debug};
 
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OrderBook {
     limit_asks: BinaryHeap<AskLimitOrder>,
     limit_bids: BinaryHeap<BidLimitOrder>,
@@ -26,7 +28,7 @@ impl OrderBook {
             return None;
         }
 
-        if data.size <= 0 {
+        if !data.size.is_positive() {
             return None;
         }
 
@@ -37,7 +39,7 @@ impl OrderBook {
         self.new_order_raw(data.side, data.price, data.size)
     }
 
-    pub fn new_order_raw(&self, side: OrderSide, price: Option<Amount>, size: i64) -> Order {
+    pub fn new_order_raw(&self, side: OrderSide, price: Option<Amount>, size: Quantity) -> Order {
         let id = *self.id.borrow();
         *self.id.borrow_mut() += 1;
 
@@ -78,6 +80,18 @@ impl OrderBook {
         }
     }
 
+    pub fn cancel_order(&mut self, id: u64) -> Option<Order> {
+        let order = self.all_orders().into_iter().find(|order| order.id == id)?;
+
+        self.limit_asks.retain(|o| o.data.id != id);
+        self.limit_bids.retain(|o| o.data.id != id);
+
+        self.market_asks.retain(|o| o.data.id != id);
+        self.market_bids.retain(|o| o.data.id != id);
+
+        Some(order)
+    }
+
     pub fn from_orders(data: Vec<Order>) -> Self {
         let mut b = Self::default();
         b.add_orders(data);
@@ -85,14 +99,56 @@ impl OrderBook {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Transaction {
     pub bid_id: u64,
     pub ask_id: u64,
-    pub size: i64,
+    pub size: Quantity,
     pub bid_loss: Amount,
     pub ask_gain: Amount,
     pub diff: Amount,
+
+    // side of the resting order, `None` when two market orders meet
+    pub maker: Option<OrderSide>,
+
+    // filled in by the market's fee schedule, zero as produced by the book
+    pub bid_fee: Amount,
+    pub ask_fee: Amount,
+    pub bid_tax: Amount,
+    pub ask_tax: Amount,
+}
+
+impl Transaction {
+    pub fn has_charges(&self) -> bool {
+        [self.bid_fee, self.ask_fee, self.bid_tax, self.ask_tax]
+            .iter()
+            .any(|x| x.as_int != 0)
+    }
+
+    // what the bidder pays, charges included
+    pub fn payment(&self) -> Amount {
+        self.bid_loss + self.bid_fee + self.bid_tax
+    }
+
+    // the part of the fill covering `units`, amounts and charges pro rata
+    pub fn partial(&self, units: Quantity) -> Transaction {
+        let part = |amount: Amount| amount.pro_rata(units, self.size).unwrap_or(amount);
+
+        let bid_loss = part(self.bid_loss);
+        let ask_gain = part(self.ask_gain);
+        Transaction {
+            size: units,
+            bid_loss,
+            ask_gain,
+            diff: bid_loss - ask_gain,
+            bid_fee: part(self.bid_fee),
+            ask_fee: part(self.ask_fee),
+            bid_tax: part(self.bid_tax),
+            ask_tax: part(self.ask_tax),
+            ..*self
+        }
+    }
 }
 
 impl Debug for Transaction {
@@ -100,12 +156,23 @@ impl Debug for Transaction {
         write!(
             f,
             "{}:{}-{}-{}:{}",
-            self.bid_id, self.bid_loss.as_int, self.size, self.ask_gain.as_int, self.ask_id
-        )
+            self.bid_id, self.bid_loss, self.size, self.ask_gain, self.ask_id
+        )?;
+
+        if self.has_charges() {
+            write!(
+                f,
+                "[fee {}/{} tax {}/{}]",
+                self.bid_fee, self.ask_fee, self.bid_tax, self.ask_tax
+            )?;
+        }
+
+        Ok(())
     }
 }
 
 impl OrderBook {
+    // matching stops at the first fill whose value doesn't fit into an amount
     pub fn match_all_market(&mut self, default_price: Option<Amount>) -> Vec<Transaction> {
         let mut transactions = Vec::new();
 
@@ -181,7 +248,7 @@ impl OrderBook {
 
         let transaction_size = best_bid.data.size.min(best_ask.data.size);
 
-        let bid_loss = default_price * transaction_size;
+        let bid_loss = default_price.checked_value(transaction_size)?;
         let ask_gain = bid_loss;
 
         let transaction = Transaction {
@@ -193,6 +260,8 @@ impl OrderBook {
             diff: Amount {
                 as_int: bid_loss.as_int - ask_gain.as_int,
             },
+            maker: None,
+            ..Default::default()
         };
 
         let mut best_bid_mut = self.market_bids.pop()?;
@@ -221,7 +290,7 @@ impl OrderBook {
 
         let transaction_size = market_order.data.size.min(best_bid.data.size);
 
-        let ask_gain = best_bid.data.price * transaction_size;
+        let ask_gain = best_bid.data.price.checked_value(transaction_size)?;
         let bid_loss = ask_gain;
 
         let transaction = Transaction {
@@ -233,6 +302,8 @@ impl OrderBook {
             diff: Amount {
                 as_int: bid_loss.as_int - ask_gain.as_int,
             },
+            maker: Some(OrderSide::Bid),
+            ..Default::default()
         };
 
         let mut best_ask_mut = self.market_asks.pop()?;
@@ -256,7 +327,7 @@ impl OrderBook {
 
         let transaction_size = market_order.data.size.min(best_ask.data.size);
 
-        let ask_gain = best_ask.data.price * transaction_size;
+        let ask_gain = best_ask.data.price.checked_value(transaction_size)?;
         let bid_loss = ask_gain;
 
         let transaction = Transaction {
@@ -268,6 +339,8 @@ impl OrderBook {
             diff: Amount {
                 as_int: bid_loss.as_int - ask_gain.as_int,
             },
+            maker: Some(OrderSide::Ask),
+            ..Default::default()
         };
 
         let mut best_bid_mut = self.market_bids.pop()?;
@@ -294,9 +367,18 @@ impl OrderBook {
             return None;
         }
 
+        // both orders rest in the book, the older one provided the liquidity
+        let maker = if (best_bid.data.timestamp, best_bid.data.id)
+            < (best_ask.data.timestamp, best_ask.data.id)
+        {
+            OrderSide::Bid
+        } else {
+            OrderSide::Ask
+        };
+
         let transaction_size = best_bid.data.size.min(best_ask.data.size);
-        let bid_loss = best_bid.data.price * transaction_size;
-        let ask_gain = best_ask.data.price * transaction_size;
+        let bid_loss = best_bid.data.price.checked_value(transaction_size)?;
+        let ask_gain = best_ask.data.price.checked_value(transaction_size)?;
 
         let transaction = Transaction {
             bid_id: best_bid.data.id,
@@ -307,6 +389,8 @@ impl OrderBook {
             diff: Amount {
                 as_int: bid_loss.as_int - ask_gain.as_int,
             },
+            maker: Some(maker),
+            ..Default::default()
         };
 
         let mut best_bid_mut = self.limit_bids.pop()?;
@@ -360,7 +444,7 @@ mod prop_tests {
             .map(|(side, price, size)| OrderData {
                 side,
                 price: Some(price),
-                size: size as i64,
+                size: Quantity::new(size as i64),
             })
             .flat_map(|data| ob.new_order_checked(data))
             .collect();
@@ -397,7 +481,7 @@ mod prop_tests {
             .map(|(side, price, size)| OrderData {
                 side: *side,
                 price: Some(*price),
-                size: size.as_int,
+                size: Quantity::new(size.as_int),
             })
             .flat_map(|data| ob.new_order_checked(data))
             .collect();
@@ -443,6 +527,44 @@ mod prop_tests {
 
         ob.all_orders().is_empty()
     }
+
+    #[quickcheck]
+    fn large_sizes_dont_wrap(order_data: Vec<(OrderSide, u8, i64)>, market_size: i64) -> bool {
+        let mut ob = OrderBook::default();
+
+        let orders: Vec<_> = order_data
+            .into_iter()
+            .map(|(side, price, size)| OrderData {
+                side,
+                price: Some(Amount {
+                    as_int: price as i64 + 1,
+                }),
+                size: Quantity::new(size.checked_abs().unwrap_or(i64::MAX)),
+            })
+            .chain([OrderData {
+                side: OrderSide::Bid,
+                price: None,
+                size: Quantity::new(market_size.checked_abs().unwrap_or(i64::MAX)),
+            }])
+            .flat_map(|data| ob.new_order_checked(data))
+            .collect();
+
+        let prices: std::collections::HashMap<_, _> =
+            orders.iter().map(|order| (order.id, order.price)).collect();
+        orders.into_iter().for_each(|order| ob.add_order(order));
+
+        let transactions = [ob.match_all_market(None), ob.match_all_limit()].concat();
+
+        // every value is the exact product of price and size
+        transactions.iter().all(|trns| {
+            let ask_price = prices[&trns.ask_id].unwrap();
+            let bid_price = prices[&trns.bid_id].unwrap_or(ask_price);
+
+            trns.ask_gain.as_int as i128 == ask_price.wide_mul(trns.size.as_int)
+                && trns.bid_loss.as_int as i128 == bid_price.wide_mul(trns.size.as_int)
+                && trns.diff.as_int >= 0
+        })
+    }
 }
 
 #[cfg(test)]
@@ -453,7 +575,7 @@ mod limit_tests {
     fn match_1v1_exact() {
         let mut ob = OrderBook::default();
 
-        let orders: Vec<_> = ["A:1:1", "B:1:1"]
+        let orders: Vec<_> = ["A:0.01:1", "B:0.01:1"]
             .into_iter()
             .flat_map(TryInto::<OrderData>::try_into)
             .flat_map(|data| ob.new_order_checked(data))
@@ -471,10 +593,12 @@ mod limit_tests {
             Some(&Transaction {
                 bid_id: 1,
                 ask_id: 0,
-                size: 1,
+                size: Quantity::new(1),
                 bid_loss: Amount { as_int: 1 },
                 ask_gain: Amount { as_int: 1 },
-                diff: Amount { as_int: 0 }
+                diff: Amount { as_int: 0 },
+                maker: Some(OrderSide::Ask),
+                ..Default::default()
             })
         );
     }
@@ -487,7 +611,7 @@ mod limit_tests {
             (OrderSide::Ask, Amount { as_int: 2 }, 1),
             (OrderSide::Bid, Amount { as_int: 1 }, 1),
         ]
-        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), size))
+        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), Quantity::new(size)))
         .into_iter()
         .for_each(|order| ob.add_order(order));
 
@@ -506,7 +630,7 @@ mod limit_tests {
             (OrderSide::Ask, Amount { as_int: 1 }, 1),
             (OrderSide::Bid, Amount { as_int: 2 }, 1),
         ]
-        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), size))
+        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), Quantity::new(size)))
         .into_iter()
         .for_each(|order| ob.add_order(order));
 
@@ -520,10 +644,12 @@ mod limit_tests {
             Some(&Transaction {
                 bid_id: 1,
                 ask_id: 0,
-                size: 1,
+                size: Quantity::new(1),
                 bid_loss: Amount { as_int: 2 },
                 ask_gain: Amount { as_int: 1 },
-                diff: Amount { as_int: 1 }
+                diff: Amount { as_int: 1 },
+                maker: Some(OrderSide::Ask),
+                ..Default::default()
             })
         );
     }
@@ -537,7 +663,7 @@ mod limit_tests {
             (OrderSide::Ask, Amount { as_int: 1 }, 1),
             (OrderSide::Bid, Amount { as_int: 1 }, 2),
         ]
-        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), size))
+        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), Quantity::new(size)))
         .into_iter()
         .for_each(|order| ob.add_order(order));
 
@@ -552,10 +678,12 @@ mod limit_tests {
             Some(&Transaction {
                 bid_id: 2,
                 ask_id: 0,
-                size: 1,
+                size: Quantity::new(1),
                 bid_loss: Amount { as_int: 1 },
                 ask_gain: Amount { as_int: 1 },
-                diff: Amount { as_int: 0 }
+                diff: Amount { as_int: 0 },
+                maker: Some(OrderSide::Ask),
+                ..Default::default()
             })
         );
 
@@ -564,10 +692,12 @@ mod limit_tests {
             Some(&Transaction {
                 bid_id: 2,
                 ask_id: 1,
-                size: 1,
+                size: Quantity::new(1),
                 bid_loss: Amount { as_int: 1 },
                 ask_gain: Amount { as_int: 1 },
-                diff: Amount { as_int: 0 }
+                diff: Amount { as_int: 0 },
+                maker: Some(OrderSide::Ask),
+                ..Default::default()
             })
         );
     }
@@ -581,7 +711,7 @@ mod limit_tests {
             (OrderSide::Bid, Amount { as_int: 1 }, 1),
             (OrderSide::Bid, Amount { as_int: 1 }, 1),
         ]
-        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), size))
+        .map(|(side, price, size)| ob.new_order_raw(side, Some(price), Quantity::new(size)))
         .into_iter()
         .for_each(|order| ob.add_order(order));
 
@@ -596,10 +726,12 @@ mod limit_tests {
             Some(&Transaction {
                 bid_id: 1,
                 ask_id: 0,
-                size: 1,
+                size: Quantity::new(1),
                 bid_loss: Amount { as_int: 1 },
                 ask_gain: Amount { as_int: 1 },
-                diff: Amount { as_int: 0 }
+                diff: Amount { as_int: 0 },
+                maker: Some(OrderSide::Ask),
+                ..Default::default()
             })
         );
 
@@ -608,10 +740,12 @@ mod limit_tests {
             Some(&Transaction {
                 bid_id: 2,
                 ask_id: 0,
-                size: 1,
+                size: Quantity::new(1),
                 bid_loss: Amount { as_int: 1 },
                 ask_gain: Amount { as_int: 1 },
-                diff: Amount { as_int: 0 }
+                diff: Amount { as_int: 0 },
+                maker: Some(OrderSide::Ask),
+                ..Default::default()
             })
         );
     }
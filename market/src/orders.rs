@@ -1,3 +1,4 @@
 pub mod flat;
 pub mod limit;
 pub mod market;
+pub mod text;
@@ -1,33 +1,43 @@
-use crate::amount::Amount;
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    amount::Amount,
+    orders::text::{ParseOrderError, parse_order_data},
+    quantity::Quantity,
+};
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OrderSide {
     Bid, // buy
     Ask, // sell
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Order {
     pub timestamp: i64,
     pub id: u64,
     pub side: OrderSide,
     pub price: Option<Amount>,
-    pub size: i64,
+    pub size: Quantity,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OrderData {
     pub side: OrderSide,
     pub price: Option<Amount>,
-    pub size: i64,
+    pub size: Quantity,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LimitOrderData {
     pub timestamp: i64,
     pub id: u64,
     pub price: Amount,
-    pub size: i64,
+    pub size: Quantity,
 }
 
 impl TryFrom<Order> for LimitOrderData {
@@ -48,10 +58,11 @@ impl TryFrom<Order> for LimitOrderData {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MarketOrderData {
     pub timestamp: i64,
     pub id: u64,
-    pub size: i64,
+    pub size: Quantity,
 }
 
 impl TryFrom<Order> for MarketOrderData {
@@ -80,48 +91,70 @@ impl From<Order> for OrderData {
     }
 }
 
+impl FromStr for OrderData {
+    type Err = ParseOrderError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        parse_order_data(value)
+    }
+}
+
 impl TryFrom<&str> for OrderData {
-    type Error = ();
+    type Error = ParseOrderError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        value.to_owned().try_into()
+        value.parse()
     }
 }
 
 impl TryFrom<String> for OrderData {
-    type Error = ();
+    type Error = ParseOrderError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        let parts: Vec<&str> = value.trim().split(":").collect();
-
-        let (side, price, size) = if let [side, price, size] = parts.as_slice() {
-            Ok((side, Some(price), size))
-        } else if let [side, size] = parts.as_slice() {
-            Ok((side, None, size))
-        } else {
-            Err(())
-        }?;
-
-        let side = if *side == "A" {
-            Ok(OrderSide::Ask)
-        } else if *side == "B" {
-            Ok(OrderSide::Bid)
-        } else {
-            return Err(());
-        }?;
-
-        let price = price
-            .into_iter()
-            .flat_map(|price| price.parse::<u64>().map_err(|_| ()))
-            .map(|x| Amount { as_int: x as i64 })
-            .next();
-
-        let size: u64 = size.parse().map_err(|_| ())?;
-
-        Ok(OrderData {
-            side,
-            price,
-            size: size as i64,
-        })
+        value.parse()
+    }
+}
+
+// parses back into the same data, "A:12.34:5" or "B:5" for market orders
+impl Display for OrderData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let side = match self.side {
+            OrderSide::Ask => "A",
+            OrderSide::Bid => "B",
+        };
+
+        match self.price {
+            Some(price) => write!(f, "{side}:{price}:{}", self.size),
+            None => write!(f, "{side}:{}", self.size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_prices() {
+        let data: OrderData = "A:12.34:5".parse().unwrap();
+        assert_eq!(data.price, Some(Amount { as_int: 1234 }));
+        assert_eq!(data.to_string(), "A:12.34:5");
+
+        let data: OrderData = "B:7.:2".parse().unwrap();
+        assert_eq!(data.price, Some(Amount::units(7)));
+        assert_eq!(data.to_string(), "B:7.00:2");
+
+        // whole numbers are whole units, as for `Amount`
+        let data: OrderData = "B:7:2".parse().unwrap();
+        assert_eq!(data.price, "7".parse().ok());
+        assert_eq!(data.to_string(), "B:7.00:2");
+
+        let data: OrderData = "B:3".parse().unwrap();
+        assert_eq!(data.price, None);
+        assert_eq!(data.to_string(), "B:3");
+
+        for invalid in ["A:1.234:5", "A:x:5", "A:1:-5", "C:1:5", "A:1:2:3"] {
+            assert!(invalid.parse::<OrderData>().is_err(), "{invalid}");
+        }
     }
 }
@@ -3,6 +3,7 @@ use crate::orders::flat::OrderSide;
 use super::flat::{LimitOrderData, Order};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LimitOrder {
     BidOrder { data: LimitOrderData },
     AskOrder { data: LimitOrderData },
@@ -46,6 +47,7 @@ impl From<LimitOrder> for Order {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BidLimitOrder {
     pub data: LimitOrderData,
 }
@@ -97,6 +99,7 @@ impl Ord for BidLimitOrder {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AskLimitOrder {
     pub data: LimitOrderData,
 }
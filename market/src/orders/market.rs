@@ -3,6 +3,7 @@ use crate::orders::flat::OrderSide;
 use super::flat::{MarketOrderData, Order};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MarketOrder {
     BidOrder { data: MarketOrderData },
     AskOrder { data: MarketOrderData },
@@ -46,6 +47,7 @@ impl From<MarketOrder> for Order {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BidMarketOrder {
     pub data: MarketOrderData,
 }
@@ -91,6 +93,7 @@ impl Ord for BidMarketOrder {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AskMarketOrder {
     pub data: MarketOrderData,
 }
@@ -0,0 +1,467 @@
+// Text notation for orders, used by tests and to drive scenarios from order scripts.
+//
+//   order    = [instrument "/"] side [":" price] ":" size {" " modifier}
+//   side     = "A" | "B"
+//   price    = decimal amount like "12.34" or "12", market orders have none
+//   size     = whole units
+//   modifier = "DAY" | "GTC" | "IOC" | "FOK" | "LMT" | "MKT" | "#" tag
+//
+// e.g. "ORE/A:12.34:5 GTC #hedge". Order types only confirm what the price says,
+// a limit order without a price or a market order with one is an error.
+// The parser takes any instrument and time in force, `Market::honoured` says
+// which orders a market can take.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    orders::flat::{OrderData, OrderSide},
+    quantity::Quantity,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseOrderError {
+    // byte offset of the offending part in the parsed text
+    pub position: usize,
+    pub message: String,
+}
+
+impl ParseOrderError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        ParseOrderError {
+            position,
+            message: message.into(),
+        }
+    }
+
+    // 1-based line and column of the error in `text`, for errors from scripts
+    pub fn location(&self, text: &str) -> (usize, usize) {
+        let before = &text[..self.position.min(text.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+}
+
+impl Display for ParseOrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseOrderError {}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimeInForce {
+    // until the end of the step
+    #[default]
+    Day,
+    GoodTillCancelled,
+    ImmediateOrCancel,
+    FillOrKill,
+}
+
+impl TimeInForce {
+    const ALL: [TimeInForce; 4] = [
+        TimeInForce::Day,
+        TimeInForce::GoodTillCancelled,
+        TimeInForce::ImmediateOrCancel,
+        TimeInForce::FillOrKill,
+    ];
+
+    fn keyword(self) -> &'static str {
+        match self {
+            TimeInForce::Day => "DAY",
+            TimeInForce::GoodTillCancelled => "GTC",
+            TimeInForce::ImmediateOrCancel => "IOC",
+            TimeInForce::FillOrKill => "FOK",
+        }
+    }
+}
+
+impl Display for TimeInForce {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.keyword())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OrderType {
+    Limit,
+    Market,
+}
+
+/// Order as written in the text notation: the data the market matches on
+/// plus what the submitter attaches to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderSpec {
+    // market the order is meant for, the only one if not given
+    pub instrument: Option<String>,
+    pub data: OrderData,
+    pub time_in_force: TimeInForce,
+    // free form labels for the submitter's bookkeeping
+    pub tags: Vec<String>,
+}
+
+impl OrderSpec {
+    pub fn new(data: OrderData) -> Self {
+        OrderSpec {
+            instrument: None,
+            data,
+            time_in_force: TimeInForce::default(),
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn order_type(&self) -> OrderType {
+        match self.data.price {
+            Some(_) => OrderType::Limit,
+            None => OrderType::Market,
+        }
+    }
+}
+
+impl From<OrderData> for OrderSpec {
+    fn from(data: OrderData) -> Self {
+        OrderSpec::new(data)
+    }
+}
+
+impl FromStr for OrderSpec {
+    type Err = ParseOrderError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        parse_words(&words(value, 0), value.len())
+    }
+}
+
+// parses back into the same spec, modifiers in canonical order
+impl Display for OrderSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(instrument) = &self.instrument {
+            write!(f, "{instrument}/")?;
+        }
+        write!(f, "{}", self.data)?;
+
+        if self.time_in_force != TimeInForce::default() {
+            write!(f, " {}", self.time_in_force)?;
+        }
+        self.tags.iter().try_for_each(|tag| write!(f, " #{tag}"))
+    }
+}
+
+/// Parses an order script, one order per line. Blank lines are skipped and
+/// `//` starts a comment that runs to the end of the line.
+pub fn parse_script(script: &str) -> Result<Vec<OrderSpec>, ParseOrderError> {
+    let mut offset = 0;
+    let mut orders = Vec::new();
+
+    for line in script.split('\n') {
+        let line_words: Vec<_> = words(line, offset)
+            .into_iter()
+            .take_while(|(_, word)| !word.starts_with("//"))
+            .collect();
+
+        if !line_words.is_empty() {
+            orders.push(parse_words(&line_words, offset + line.len())?);
+        }
+        offset += line.len() + 1;
+    }
+
+    Ok(orders)
+}
+
+// the side, price and size of a plain order, no instrument or modifiers
+pub(crate) fn parse_order_data(value: &str) -> Result<OrderData, ParseOrderError> {
+    let words = words(value, 0);
+    let Some(&(start, word)) = words.first() else {
+        return Err(ParseOrderError::new(value.len(), "empty order"));
+    };
+    if let Some(&(position, extra)) = words.get(1) {
+        return Err(ParseOrderError::new(
+            position,
+            format!("unexpected '{extra}', plain orders have no modifiers"),
+        ));
+    }
+    if word.contains('/') {
+        return Err(ParseOrderError::new(
+            start,
+            "plain orders have no instrument",
+        ));
+    }
+
+    parse_fields(start, word)
+}
+
+// whitespace separated words with their offsets, shifted by `offset`
+fn words(text: &str, offset: usize) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                words.push((offset + s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    words
+}
+
+fn parse_words(words: &[(usize, &str)], end: usize) -> Result<OrderSpec, ParseOrderError> {
+    let Some(&(start, word)) = words.first() else {
+        return Err(ParseOrderError::new(end, "empty order"));
+    };
+
+    let (instrument, start, word) = match word.split_once('/') {
+        Some(("", _)) => return Err(ParseOrderError::new(start, "missing instrument")),
+        Some((instrument, rest)) => (
+            Some(instrument.to_owned()),
+            start + instrument.len() + 1,
+            rest,
+        ),
+        None => (None, start, word),
+    };
+
+    let mut spec = OrderSpec {
+        instrument,
+        ..OrderSpec::new(parse_fields(start, word)?)
+    };
+
+    let mut time_in_force = None;
+    let mut order_type = None;
+    for &(position, word) in &words[1..] {
+        if let Some(tag) = word.strip_prefix('#') {
+            if tag.is_empty() {
+                return Err(ParseOrderError::new(position, "empty tag"));
+            }
+            spec.tags.push(tag.to_owned());
+        } else if let Some(tif) = TimeInForce::ALL.into_iter().find(|t| t.keyword() == word) {
+            if time_in_force.replace(tif).is_some() {
+                return Err(ParseOrderError::new(position, "time in force given twice"));
+            }
+        } else if let Some(kind) = match word {
+            "LMT" => Some(OrderType::Limit),
+            "MKT" => Some(OrderType::Market),
+            _ => None,
+        } {
+            if order_type.replace(kind).is_some() {
+                return Err(ParseOrderError::new(position, "order type given twice"));
+            }
+            if kind != spec.order_type() {
+                let message = match kind {
+                    OrderType::Limit => "limit order without a price",
+                    OrderType::Market => "market order with a price",
+                };
+                return Err(ParseOrderError::new(position, message));
+            }
+        } else {
+            return Err(ParseOrderError::new(
+                position,
+                format!("unknown modifier '{word}'"),
+            ));
+        }
+    }
+    spec.time_in_force = time_in_force.unwrap_or_default();
+
+    Ok(spec)
+}
+
+// "side[:price]:size" starting at `start`
+fn parse_fields(start: usize, word: &str) -> Result<OrderData, ParseOrderError> {
+    let mut offset = start;
+    let fields: Vec<_> = word
+        .split(':')
+        .map(|field| {
+            let position = offset;
+            offset += field.len() + 1;
+            (position, field)
+        })
+        .collect();
+
+    let (side, price, size) = match fields.as_slice() {
+        [side, price, size] => (side, Some(price), size),
+        [side, size] => (side, None, size),
+        [_] => {
+            return Err(ParseOrderError::new(
+                start + word.len(),
+                "missing size, expected side[:price]:size",
+            ));
+        }
+        [.., extra] => {
+            return Err(ParseOrderError::new(
+                extra.0,
+                "too many fields, expected side[:price]:size",
+            ));
+        }
+        [] => unreachable!("split yields at least one field"),
+    };
+
+    let side = match side.1 {
+        "A" => OrderSide::Ask,
+        "B" => OrderSide::Bid,
+        other => {
+            return Err(ParseOrderError::new(
+                side.0,
+                format!("unknown side '{other}', expected A or B"),
+            ));
+        }
+    };
+
+    let price = match price {
+        Some(&(position, price)) => Some(price.parse().map_err(|err| {
+            ParseOrderError::new(position, format!("invalid price '{price}': {err}"))
+        })?),
+        None => None,
+    };
+
+    let (position, size) = *size;
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseOrderError::new(
+            position,
+            format!("invalid size '{size}', expected whole units"),
+        ));
+    }
+    let size = size
+        .parse()
+        .map_err(|_| ParseOrderError::new(position, format!("size '{size}' out of range")))?;
+
+    Ok(OrderData {
+        side,
+        price,
+        size: Quantity::new(size),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::{amount::Amount, test_util};
+
+    #[test]
+    fn full_notation() {
+        let spec: OrderSpec = "ORE/A:12.34:5 GTC LMT #hedge #desk-2".parse().unwrap();
+        assert_eq!(spec.instrument.as_deref(), Some("ORE"));
+        assert_eq!(spec.data, "A:12.34:5".parse().unwrap());
+        assert_eq!(spec.time_in_force, TimeInForce::GoodTillCancelled);
+        assert_eq!(spec.order_type(), OrderType::Limit);
+        assert_eq!(spec.tags, ["hedge", "desk-2"]);
+        assert_eq!(spec.to_string(), "ORE/A:12.34:5 GTC #hedge #desk-2");
+
+        let spec: OrderSpec = "  B:3 MKT IOC ".parse().unwrap();
+        assert_eq!(spec.order_type(), OrderType::Market);
+        assert_eq!(spec.to_string(), "B:3 IOC");
+        assert_eq!("B:3 DAY".parse::<OrderSpec>().unwrap().to_string(), "B:3");
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        for (text, position, message) in [
+            ("", 0, "empty order"),
+            ("C:1:5", 0, "unknown side 'C', expected A or B"),
+            (
+                "ORE/A:x:5",
+                6,
+                "invalid price 'x': can't parse amount: invalid digit",
+            ),
+            (
+                "A:1.234:5",
+                2,
+                "invalid price '1.234': can't parse amount: too many decimal places",
+            ),
+            ("A:1:-5", 4, "invalid size '-5', expected whole units"),
+            (
+                "A:1:99999999999999999999",
+                4,
+                "size '99999999999999999999' out of range",
+            ),
+            ("A", 1, "missing size, expected side[:price]:size"),
+            ("A:1:2:3", 6, "too many fields, expected side[:price]:size"),
+            ("/A:1:2", 0, "missing instrument"),
+            ("A:1:2 GTC FOK", 10, "time in force given twice"),
+            ("B:2 LMT", 4, "limit order without a price"),
+            ("B:1:2 MKT", 6, "market order with a price"),
+            ("B:1:2 #", 6, "empty tag"),
+            ("B:1:2 NOW", 6, "unknown modifier 'NOW'"),
+        ] {
+            assert_eq!(
+                text.parse::<OrderSpec>(),
+                Err(ParseOrderError::new(position, message)),
+                "{text}"
+            );
+        }
+
+        // plain order data takes the bare fields only
+        let err = "A:1:2 GTC".parse::<OrderData>().unwrap_err();
+        assert_eq!(err.position, 6);
+        assert!("ORE/A:1:2".parse::<OrderData>().is_err());
+    }
+
+    #[test]
+    fn markets_honour_their_instrument_for_the_step() {
+        let market = test_util::market("ORE");
+
+        for (text, honoured) in [
+            ("A:1:2", true),
+            ("ORE/A:1:2 DAY", true),
+            ("GOLD/A:1:2", false),
+            ("ORE/B:2 IOC", false),
+            ("B:1:2 GTC", false),
+            ("B:1:2 FOK", false),
+        ] {
+            let spec: OrderSpec = text.parse().unwrap();
+            let data = honoured.then_some(spec.data);
+            assert_eq!(market.honoured(&spec), data, "{text}");
+        }
+    }
+
+    #[test]
+    fn scripts() {
+        let script = "
+            // opening auction
+            A:0.10:5 #open
+            B:0.12:5   // crosses
+
+            B:2 IOC
+            A:0.11 GTC
+        ";
+
+        let err = parse_script(script).unwrap_err();
+        assert_eq!(err.location(script), (7, 15));
+        assert_eq!(err.message, "invalid size '0.11', expected whole units");
+
+        let orders = parse_script(&script.replace("A:0.11", "A:0.11:1")).unwrap();
+        assert_eq!(
+            orders.iter().map(|o| o.to_string()).collect::<Vec<_>>(),
+            ["A:0.10:5 #open", "B:0.12:5", "B:2 IOC", "A:0.11:1 GTC"]
+        );
+        assert_eq!(parse_script("  // nothing\n\n"), Ok(vec![]));
+    }
+
+    #[quickcheck]
+    fn round_trips(side: OrderSide, price: Option<i64>, size: u32, tif: u8, tags: Vec<u8>) -> bool {
+        let spec = OrderSpec {
+            instrument: (tif % 2 == 0).then(|| "ORE".to_owned()),
+            data: OrderData {
+                side,
+                price: price.map(|as_int| Amount { as_int }),
+                size: Quantity::new(size as i64),
+            },
+            time_in_force: TimeInForce::ALL[tif as usize % 4],
+            tags: tags.iter().map(|tag| format!("t{tag}")).collect(),
+        };
+
+        spec.to_string().parse() == Ok(spec)
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{amount::Amount, order_book::Transaction, orders::flat::OrderSide, quantity::Quantity};
+
+/// Trading record of one agent built from its fills. Position, cost basis and
+/// realised PnL only cover traded units, endowments enter through the equity,
+/// which is the account's equity at the reference price marked every step.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Portfolio {
+    // signed, negative when more was sold than bought
+    pub position: Quantity,
+    // what the open position cost, negative for a short position
+    pub cost: Amount,
+    // closed out profits net of all fees and taxes
+    pub realised: Amount,
+    // traded value on both sides
+    pub turnover: Amount,
+    // last marked equity, `None` before the first mark
+    pub equity: Option<Amount>,
+    // highest marked equity
+    pub peak: Amount,
+    pub max_drawdown: Amount,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortfolioStats {
+    pub position: Quantity,
+    pub average_cost: Option<Amount>,
+    pub realised: Amount,
+    pub unrealised: Amount,
+    pub equity: Amount,
+    pub turnover: Amount,
+    pub max_drawdown: Amount,
+}
+
+impl Portfolio {
+    pub fn fill(&mut self, side: OrderSide, transaction: &Transaction) {
+        let (qty, value, charges) = match side {
+            OrderSide::Bid => (
+                transaction.size,
+                transaction.bid_loss,
+                transaction.bid_fee + transaction.bid_tax,
+            ),
+            OrderSide::Ask => (
+                -transaction.size,
+                transaction.ask_gain,
+                transaction.ask_fee + transaction.ask_tax,
+            ),
+        };
+
+        self.turnover += value;
+        self.realised -= charges;
+
+        let closing = if self.position.signum() == -qty.signum() {
+            qty.abs().min(self.position.abs())
+        } else {
+            Quantity::ZERO
+        };
+
+        let closing_value = value.pro_rata(closing, qty.abs()).unwrap_or(value);
+        if closing.is_positive() {
+            let released = self
+                .cost
+                .pro_rata(closing, self.position.abs())
+                .unwrap_or(self.cost);
+
+            self.realised += closing_value * self.position.signum() - released;
+            self.cost -= released;
+            self.position += closing * qty.signum();
+        }
+
+        let opening = qty.abs() - closing;
+        if opening.is_positive() {
+            self.cost += (value - closing_value) * qty.signum();
+            self.position += opening * qty.signum();
+        }
+    }
+
+    pub fn average_cost(&self) -> Option<Amount> {
+        if self.position == Quantity::ZERO {
+            None
+        } else {
+            Some(Amount {
+                as_int: self.cost.as_int / self.position.as_int,
+            })
+        }
+    }
+
+    // saturating where it doesn't fit
+    pub fn unrealised(&self, reference_price: Amount) -> Amount {
+        let value = reference_price.wide_mul(self.position.as_int);
+        Amount::saturating_from_wide(value - self.cost.as_int as i128)
+    }
+
+    pub fn mark(&mut self, equity: Amount) {
+        self.peak = match self.equity {
+            Some(_) => self.peak.max(equity),
+            None => equity,
+        };
+        self.equity = Some(equity);
+        self.max_drawdown = self.max_drawdown.max(self.peak - equity);
+    }
+
+    pub fn stats(&self, reference_price: Amount) -> PortfolioStats {
+        PortfolioStats {
+            position: self.position,
+            average_cost: self.average_cost(),
+            realised: self.realised,
+            unrealised: self.unrealised(reference_price),
+            equity: self.equity.unwrap_or_default(),
+            turnover: self.turnover,
+            max_drawdown: self.max_drawdown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::Account,
+        lending::LendingPolicy,
+        test_util::{self, trade},
+    };
+
+    fn fill(side: OrderSide, size: i64, price: i64) -> (OrderSide, Transaction) {
+        let value = Amount {
+            as_int: price * size,
+        };
+        let trns = Transaction {
+            size: Quantity::new(size),
+            bid_loss: value,
+            ask_gain: value,
+            ..Default::default()
+        };
+        (side, trns)
+    }
+
+    #[test]
+    fn average_cost_and_realised() {
+        let mut portfolio = Portfolio::default();
+
+        [
+            fill(OrderSide::Bid, 2, 10),
+            fill(OrderSide::Bid, 2, 20),
+            fill(OrderSide::Ask, 1, 30),
+        ]
+        .iter()
+        .for_each(|(side, trns)| portfolio.fill(*side, trns));
+
+        assert_eq!(portfolio.position, Quantity::new(3));
+        assert_eq!(portfolio.average_cost(), Some(Amount { as_int: 15 }));
+        assert_eq!(portfolio.realised, Amount { as_int: 15 });
+        assert_eq!(portfolio.turnover, Amount { as_int: 90 });
+        assert_eq!(
+            portfolio.unrealised(Amount { as_int: 20 }),
+            Amount { as_int: 15 }
+        );
+
+        // flipping to a short position realises the rest and opens at the fill price
+        let (side, trns) = fill(OrderSide::Ask, 5, 10);
+        portfolio.fill(side, &trns);
+
+        assert_eq!(portfolio.position, Quantity::new(-2));
+        assert_eq!(portfolio.average_cost(), Some(Amount { as_int: 10 }));
+        assert_eq!(portfolio.realised, Amount { as_int: 15 - 15 });
+
+        let (side, trns) = fill(OrderSide::Bid, 2, 4);
+        portfolio.fill(side, &trns);
+
+        assert_eq!(portfolio.position, Quantity::ZERO);
+        assert_eq!(portfolio.cost, Amount::new());
+        assert_eq!(portfolio.realised, Amount { as_int: 12 });
+    }
+
+    #[test]
+    fn extreme_marks_saturate() {
+        let mut portfolio = Portfolio::default();
+        let (side, trns) = fill(OrderSide::Bid, 4, 10);
+        portfolio.fill(side, &trns);
+        assert_eq!(portfolio.unrealised(Amount::MAX), Amount::MAX);
+        assert_eq!(portfolio.unrealised(Amount::MIN), Amount::MIN);
+
+        let account = Account {
+            commodity: Quantity::new(4),
+            borrowed_commodity: Quantity::new(1),
+            money: Amount { as_int: -10 },
+            ..Default::default()
+        };
+        assert_eq!(
+            account.equity(Amount {
+                as_int: i64::MAX / 2
+            }),
+            Amount::MAX
+        );
+        assert_eq!(account.equity(Amount::MIN), Amount::MIN);
+
+        // what is owed cancels what is held before anything saturates
+        let hedged = Account {
+            borrowed_commodity: Quantity::new(4),
+            ..account
+        };
+        assert_eq!(hedged.equity(Amount::MAX), Amount { as_int: -10 });
+    }
+
+    #[test]
+    fn drawdown() {
+        let mut portfolio = Portfolio::default();
+        [100, 120, 90, 110, 80, 130]
+            .into_iter()
+            .for_each(|as_int| portfolio.mark(Amount { as_int }));
+
+        assert_eq!(portfolio.max_drawdown, Amount { as_int: 40 });
+        assert_eq!(portfolio.peak, Amount { as_int: 130 });
+        assert_eq!(
+            portfolio.stats(Amount::new()).equity,
+            Amount { as_int: 130 }
+        );
+    }
+
+    #[test]
+    fn tracked_by_market() {
+        let mut market = test_util::market("portfolio").with_lending(LendingPolicy::default());
+        market.fund_lending_pool(Quantity::new(10));
+
+        let trader = market.register_with_acc(Account {
+            money: Amount { as_int: 100 },
+            ..Default::default()
+        });
+        let other = market.register_with_acc(Account {
+            money: Amount { as_int: 100 },
+            commodity: Quantity::new(10),
+            ..Default::default()
+        });
+
+        trade(&mut market, &[(trader, "A:0.10:3"), (other, "B:0.10:3")]);
+        market.mark_portfolios(Amount { as_int: 10 });
+        trade(&mut market, &[(other, "A:0.06:3"), (trader, "B:0.06:3")]);
+        market.mark_portfolios(Amount { as_int: 6 });
+
+        let stats = market
+            .portfolio_stats(trader, Amount { as_int: 6 })
+            .unwrap();
+        assert_eq!(stats.position, Quantity::ZERO);
+        assert_eq!(stats.realised, Amount { as_int: 12 });
+        assert_eq!(stats.turnover, Amount { as_int: 48 });
+        assert_eq!(stats.equity, Amount { as_int: 112 });
+
+        let stats = market.portfolio_stats(other, Amount { as_int: 6 }).unwrap();
+        assert_eq!(stats.realised, Amount { as_int: -12 });
+        // the endowment lost value too
+        assert_eq!(stats.max_drawdown, Amount { as_int: 200 - 148 });
+    }
+}
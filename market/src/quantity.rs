@@ -0,0 +1,257 @@
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+    iter::Sum,
+    marker::PhantomData,
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+use crate::amount::Amount;
+
+/// Unit a commodity is counted in. Markets count in plain `Units`, scenarios can
+/// tag their own quantities and `cast` them where they meet the market.
+pub trait Unit: Copy + Debug + Default + Eq + Hash + Ord {
+    const SYMBOL: &'static str = "";
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Units;
+
+impl Unit for Units {}
+
+/// Amount of commodity, kept apart from money so the two can't be mixed up.
+/// Price times quantity is the only way from one to the other.
+// serialised as the bare number, the unit is part of the type
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent, bound = "")
+)]
+pub struct Quantity<U: Unit = Units> {
+    pub as_int: i64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _unit: PhantomData<U>,
+}
+
+impl<U: Unit> Quantity<U> {
+    pub const ZERO: Self = Self::new(0);
+    pub const MAX: Self = Self::new(i64::MAX);
+
+    pub const fn new(as_int: i64) -> Self {
+        Quantity {
+            as_int,
+            _unit: PhantomData,
+        }
+    }
+
+    // the same number counted in another unit, conversions are up to the caller
+    pub fn cast<V: Unit>(self) -> Quantity<V> {
+        Quantity::new(self.as_int)
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.as_int > 0
+    }
+
+    pub fn abs(self) -> Self {
+        Self::new(self.as_int.abs())
+    }
+
+    pub fn signum(self) -> i64 {
+        self.as_int.signum()
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.as_int.checked_add(rhs.as_int).map(Self::new)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.as_int.checked_sub(rhs.as_int).map(Self::new)
+    }
+
+    pub fn checked_mul(self, rhs: i64) -> Option<Self> {
+        self.as_int.checked_mul(rhs).map(Self::new)
+    }
+
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self::new(self.as_int.saturating_add(rhs.as_int))
+    }
+
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self::new(self.as_int.saturating_sub(rhs.as_int))
+    }
+}
+
+impl<U: Unit> Debug for Quantity<U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl<U: Unit> Display for Quantity<U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_int)?;
+        if !U::SYMBOL.is_empty() {
+            write!(f, " {}", U::SYMBOL)?;
+        }
+        Ok(())
+    }
+}
+
+// plain integers, the unit symbol isn't part of the notation
+impl<U: Unit> FromStr for Quantity<U> {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value.trim().parse().map(Self::new)
+    }
+}
+
+impl<U: Unit> Add for Quantity<U> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.as_int + rhs.as_int)
+    }
+}
+
+impl<U: Unit> AddAssign for Quantity<U> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.as_int += rhs.as_int;
+    }
+}
+
+impl<U: Unit> Sub for Quantity<U> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.as_int - rhs.as_int)
+    }
+}
+
+impl<U: Unit> SubAssign for Quantity<U> {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.as_int -= rhs.as_int;
+    }
+}
+
+impl<U: Unit> Neg for Quantity<U> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::new(-self.as_int)
+    }
+}
+
+impl<U: Unit> Mul<i64> for Quantity<U> {
+    type Output = Self;
+
+    fn mul(self, rhs: i64) -> Self::Output {
+        Self::new(self.as_int * rhs)
+    }
+}
+
+impl<U: Unit> Sum for Quantity<U> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+
+// value at a price, panics where it doesn't fit
+impl<U: Unit, const SCALE: u32> Mul<Quantity<U>> for Amount<SCALE> {
+    type Output = Self;
+
+    fn mul(self, rhs: Quantity<U>) -> Self {
+        self.checked_value(rhs)
+            .expect("value out of range of the amount")
+    }
+}
+
+impl<const SCALE: u32> Amount<SCALE> {
+    // value of `quantity` at this price, None where it doesn't fit
+    pub fn checked_value<U: Unit>(self, quantity: Quantity<U>) -> Option<Self> {
+        self.checked_mul(quantity.as_int)
+    }
+
+    pub fn saturating_value<U: Unit>(self, quantity: Quantity<U>) -> Self {
+        self.saturating_mul(quantity.as_int)
+    }
+
+    // `self * part / whole` for a share of a fill or a position
+    pub fn pro_rata<U: Unit>(self, part: Quantity<U>, whole: Quantity<U>) -> Option<Self> {
+        self.mul_div(
+            part.as_int,
+            whole.as_int,
+            crate::amount::Rounding::TowardZero,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    struct Tonnes;
+
+    impl Unit for Tonnes {
+        const SYMBOL: &'static str = "t";
+    }
+
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    struct Litres;
+
+    impl Unit for Litres {
+        const SYMBOL: &'static str = "l";
+    }
+
+    #[test]
+    fn units_are_tracked() {
+        let ore = Quantity::<Tonnes>::new(3) + Quantity::new(4);
+        assert_eq!(ore.to_string(), "7 t");
+        assert_eq!(Quantity::<Units>::new(7).to_string(), "7");
+        assert_eq!("12".parse(), Ok(Quantity::<Litres>::new(12)));
+
+        // tonnes and litres only meet through an explicit cast
+        let fuel: Quantity<Litres> = ore.cast();
+        assert_eq!(fuel.as_int, 7);
+
+        let price: Amount = Amount { as_int: 250 };
+        assert_eq!(price * ore, Amount { as_int: 1750 });
+        assert_eq!(price.checked_value(fuel), Some(Amount { as_int: 1750 }));
+        assert_eq!(price.checked_value(Quantity::<Tonnes>::MAX), None);
+        assert_eq!(price.saturating_value(Quantity::<Tonnes>::MAX), Amount::MAX);
+        assert_eq!(
+            (-price).saturating_value(Quantity::<Tonnes>::MAX),
+            Amount::MIN
+        );
+        assert_eq!(
+            price.pro_rata(Quantity::<Tonnes>::new(2), ore),
+            Some(Amount { as_int: 71 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "value out of range")]
+    fn value_out_of_range() {
+        let _ = Amount::<2>::ONE * Quantity::<Tonnes>::MAX;
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let max = Quantity::<Units>::MAX;
+        assert_eq!(max.checked_add(Quantity::new(1)), None);
+        assert_eq!(max.saturating_add(Quantity::new(1)), max);
+        assert_eq!(Quantity::<Units>::ZERO.checked_sub(max), Some(-max));
+        assert_eq!(max.checked_mul(2), None);
+        assert_eq!(
+            [1, 2, 3]
+                .map(Quantity::<Units>::new)
+                .into_iter()
+                .sum::<Quantity>(),
+            Quantity::new(6)
+        );
+    }
+}
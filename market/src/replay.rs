@@ -0,0 +1,364 @@
+use crate::{
+    account::Account,
+    agent::AgentId,
+    amount::Amount,
+    bankruptcy::Settlement,
+    engine::MatchingEngine,
+    ledger::Holder,
+    market::Market,
+    order_book::Transaction,
+    orders::flat::{Order, OrderData},
+    quantity::Quantity,
+};
+
+// a call that changed the market, with the arguments it was made with
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    Register(Account),
+    Deregister(AgentId, Settlement),
+    InjectMoney(AgentId, Amount),
+    InjectCommodity(AgentId, Quantity),
+    Produce(AgentId, Quantity, Amount),
+    SetCreditLimit(AgentId, Amount),
+    FundLendingPool(Quantity),
+    Recall(Quantity),
+    CreateOrders(AgentId, Vec<OrderData>),
+    SubmitOrder(AgentId, Order),
+    ProcessSubmittedOrders(Option<Amount>),
+    ClearReservesAndOrders,
+    SettleObligations,
+    CheckBankruptcies(Amount),
+    MarkPortfolios(Amount),
+    AccrueInterest,
+    CheckMargins(Amount),
+    SettleLending(Amount),
+}
+
+impl Event {
+    pub fn apply<CommodityType, Engine: MatchingEngine>(
+        &self,
+        market: &mut Market<CommodityType, Engine>,
+    ) {
+        match self.clone() {
+            Event::Register(account) => {
+                market.register_with_acc(account);
+            }
+            Event::Deregister(id, settlement) => {
+                market.deregister(id, settlement);
+            }
+            Event::InjectMoney(id, money) => {
+                market.inject_money(id, money);
+            }
+            Event::InjectCommodity(id, units) => {
+                market.inject_commodity(id, units);
+            }
+            Event::Produce(id, units, cost) => {
+                market.produce(id, units, cost);
+            }
+            Event::SetCreditLimit(id, limit) => {
+                market.set_credit_limit(id, limit);
+            }
+            Event::FundLendingPool(units) => market.fund_lending_pool(units),
+            Event::Recall(units) => {
+                market.recall(units);
+            }
+            Event::CreateOrders(id, orders) => {
+                market.create_orders(&id, &orders);
+            }
+            Event::SubmitOrder(id, order) => {
+                market.submit_order(&id, order);
+            }
+            Event::ProcessSubmittedOrders(price) => {
+                market.process_submitted_orders(price);
+            }
+            Event::ClearReservesAndOrders => market.clear_reserves_and_orders(),
+            Event::SettleObligations => {
+                market.settle_obligations();
+            }
+            Event::CheckBankruptcies(price) => {
+                market.check_bankruptcies(price);
+            }
+            Event::MarkPortfolios(price) => market.mark_portfolios(price),
+            Event::AccrueInterest => market.accrue_interest(),
+            Event::CheckMargins(price) => {
+                market.check_margins(price);
+            }
+            Event::SettleLending(price) => market.settle_lending(price),
+        }
+    }
+}
+
+/// Everything that happened to the market in one step, starting with `Market::start_step`.
+/// Calls made before the first step are kept in step zero.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoggedStep {
+    pub step: u64,
+    pub events: Vec<Event>,
+    // what the events produced, checked on replay
+    pub transactions: Vec<Transaction>,
+    // the market account and the agents by id as the step ended
+    pub closing: Vec<(Holder, Account)>,
+}
+
+/// Input log of a market: enough to rebuild it without the agents that drove it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventLog {
+    steps: Vec<LoggedStep>,
+}
+
+impl EventLog {
+    pub fn steps(&self) -> &[LoggedStep] {
+        &self.steps
+    }
+
+    pub(crate) fn record(&mut self, event: Event) {
+        self.current().events.push(event);
+    }
+
+    pub(crate) fn record_transactions(&mut self, transactions: &[Transaction]) {
+        self.current().transactions.extend_from_slice(transactions);
+    }
+
+    pub(crate) fn start_step(&mut self, step: u64, closing: Vec<(Holder, Account)>) {
+        self.close(closing);
+        self.steps.push(LoggedStep {
+            step,
+            ..Default::default()
+        });
+    }
+
+    pub(crate) fn close(&mut self, closing: Vec<(Holder, Account)>) {
+        self.current().closing = closing;
+    }
+
+    fn current(&mut self) -> &mut LoggedStep {
+        if self.steps.is_empty() {
+            self.steps.push(LoggedStep::default());
+        }
+        self.steps.last_mut().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DivergenceKind {
+    Transactions {
+        expected: Vec<Transaction>,
+        actual: Vec<Transaction>,
+    },
+    // the first holder whose account differs, `None` where it is missing
+    Account {
+        holder: Holder,
+        expected: Option<Account>,
+        actual: Option<Account>,
+    },
+}
+
+// the first step where the replay didn't come out as recorded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: u64,
+    pub kind: Box<DivergenceKind>,
+}
+
+/// Feeds the log into `market`, which has to be configured like the recorded one and
+/// have no agents yet. Stops at the first step whose transactions or closing accounts
+/// differ from the recorded ones.
+pub fn replay<CommodityType, Engine: MatchingEngine>(
+    market: &mut Market<CommodityType, Engine>,
+    log: &EventLog,
+) -> Result<(), Divergence> {
+    for logged in log.steps() {
+        market.start_step(logged.step);
+
+        let mut transactions = Vec::new();
+        for event in &logged.events {
+            match event {
+                Event::ProcessSubmittedOrders(price) => {
+                    transactions.extend(market.process_submitted_orders(*price));
+                }
+                event => event.apply(market),
+            }
+        }
+
+        let diverged = |kind| {
+            Err(Divergence {
+                step: logged.step,
+                kind: Box::new(kind),
+            })
+        };
+
+        if transactions != logged.transactions {
+            return diverged(DivergenceKind::Transactions {
+                expected: logged.transactions.clone(),
+                actual: transactions,
+            });
+        }
+
+        let closing = market.all_accounts();
+        let holders = logged.closing.iter().chain(&closing).map(|(h, _)| *h);
+        for holder in holders {
+            let find = |accounts: &[(Holder, Account)]| {
+                accounts.iter().find(|(h, _)| *h == holder).map(|(_, a)| *a)
+            };
+            let (expected, actual) = (find(&logged.closing), find(&closing));
+
+            if expected != actual {
+                return diverged(DivergenceKind::Account {
+                    holder,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{
+        agent::Agent,
+        credit::CreditPolicy,
+        market::{History, MarketInfo},
+        rng::SimRng,
+        settlement::SettlementPolicy,
+    };
+
+    // bids and asks around a price that walks with every step
+    struct Noise {
+        state: u64,
+    }
+
+    impl Agent for Noise {
+        type CommodityType = ();
+
+        fn setup(&mut self, _id: AgentId, _info: &MarketInfo<()>, _rng: SimRng) {}
+
+        fn produce_orders(
+            &mut self,
+            _account: &Account,
+            _info: &MarketInfo<()>,
+            _history: &History,
+        ) -> Vec<OrderData> {
+            self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            let draw = self.state >> 33;
+            let side = if draw % 2 == 0 { "A" } else { "B" };
+            let price: Amount = Amount {
+                as_int: 40 + (draw % 21) as i64,
+            };
+
+            vec![
+                format!("{side}:{price}:{}", 1 + draw % 3).parse().unwrap(),
+                format!("{side}:1").parse().unwrap(),
+            ]
+        }
+    }
+
+    fn market() -> Market<()> {
+        Market::new(MarketInfo {
+            name: "replay".to_owned(),
+            commodity: (),
+        })
+        .with_credit(CreditPolicy {
+            interest_bps: 100,
+            ..Default::default()
+        })
+        .with_settlement(SettlementPolicy {
+            lag: 1,
+            ..Default::default()
+        })
+    }
+
+    // runs a few agents with the per step calls of a simulation
+    fn run(steps: u64) -> Market<()> {
+        let mut market = market().with_event_log();
+        let agents: Vec<(AgentId, Market<()>::AgentRefType)> = (1..=4)
+            .map(|state| {
+                let id = market.register_with_acc(Account {
+                    commodity: Quantity::new(20),
+                    money: Amount::units(20),
+                    credit_limit: Amount::units(5),
+                    ..Default::default()
+                });
+                let agent: Market<()>::AgentRefType = RefCell::new(Box::new(Noise { state }));
+                (id, agent)
+            })
+            .collect();
+
+        let mut history = History::default();
+        for step in 1..=steps {
+            market.start_step(step);
+            market.settle_obligations();
+            if step == 3 {
+                market.inject_money(agents[0].0, Amount::units(5));
+            }
+
+            market.agents_submit_orders(&agents, &history);
+            let transactions = market.process_submitted_orders(history.market_price());
+            market.clear_reserves_and_orders();
+            market.accrue_interest();
+
+            history = History {
+                step,
+                transactions,
+                ..Default::default()
+            };
+        }
+
+        market
+    }
+
+    #[test]
+    fn replay_reproduces_the_run() {
+        let recorded = run(12);
+        let log = recorded.event_log().unwrap();
+        assert_eq!(log.steps().len(), 13);
+        assert!(log.steps().iter().any(|step| !step.transactions.is_empty()));
+
+        let mut replayed = market();
+        assert_eq!(replay(&mut replayed, &log), Ok(()));
+        assert_eq!(replayed.all_accounts(), recorded.all_accounts());
+        assert_eq!(replayed.ledger().postings(), recorded.ledger().postings());
+    }
+
+    #[test]
+    fn divergence_is_reported_at_the_first_step() {
+        let log = run(8).event_log().unwrap();
+
+        // a different fee schedule changes the first trade
+        let first = log
+            .steps()
+            .iter()
+            .find(|step| !step.transactions.is_empty())
+            .unwrap()
+            .step;
+        let mut replayed = market().with_fees(crate::fees::FeeSchedule {
+            tax: crate::fees::Fee::fixed(Amount { as_int: 1 }),
+            ..Default::default()
+        });
+        let divergence = replay(&mut replayed, &log).unwrap_err();
+        assert_eq!(divergence.step, first);
+        assert!(matches!(
+            *divergence.kind,
+            DivergenceKind::Transactions { .. }
+        ));
+
+        // a tampered injection shows up in the balances of its step
+        let mut tampered = log.clone();
+        let step = tampered.steps.iter_mut().find(|s| s.step == 3).unwrap();
+        step.events.iter_mut().for_each(|event| {
+            if let Event::InjectMoney(_, money) = event {
+                *money = Amount::units(6);
+            }
+        });
+        let divergence = replay(&mut market(), &tampered).unwrap_err();
+        assert_eq!(divergence.step, 3);
+    }
+}
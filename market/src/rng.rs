@@ -0,0 +1,167 @@
+use std::ops::RangeInclusive;
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(GOLDEN_GAMMA);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// xoshiro256** seeded through SplitMix64. Only integer arithmetic goes into the
+/// stream, so a seed gives the same numbers on every target, wasm included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimRng {
+    state: [u64; 4],
+}
+
+impl SimRng {
+    pub fn seed_from_u64(seed: u64) -> Self {
+        let mut sm = seed;
+        Self {
+            state: std::array::from_fn(|_| splitmix64(&mut sm)),
+        }
+    }
+
+    // independent stream number `stream` of a master seed
+    pub fn stream(seed: u64, stream: u64) -> Self {
+        let mut sm = stream;
+        Self::seed_from_u64(seed ^ splitmix64(&mut sm))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    // uniform in [0, 1) with the 53 bits a double holds
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    // uniform in [0, bound), rejecting the biased tail
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "can't draw below zero");
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % bound;
+            }
+        }
+    }
+
+    pub fn range(&mut self, range: RangeInclusive<i64>) -> i64 {
+        let (start, end) = range.into_inner();
+        assert!(start <= end, "empty range {start}..={end}");
+
+        let span = end.abs_diff(start);
+        let offset = match span.checked_add(1) {
+            Some(bound) => self.below(bound),
+            None => self.next_u64(),
+        };
+        start.wrapping_add_unsigned(offset)
+    }
+
+    // approximately standard normal, the sum of twelve uniforms keeps it to
+    // arithmetic that rounds the same everywhere
+    pub fn standard_normal(&mut self) -> f64 {
+        (0..12).map(|_| self.next_f64()).sum::<f64>() - 6.0
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.below(items.len() as u64) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::AgentId,
+        market::{Market, MarketInfo},
+    };
+
+    #[test]
+    fn matches_the_reference_streams() {
+        // SplitMix64 from zero, as published with the algorithm
+        let mut sm = 0;
+        assert_eq!(splitmix64(&mut sm), 0xe220_a839_7b1d_cdaf);
+
+        let mut rng = SimRng::seed_from_u64(0);
+        let first: Vec<u64> = (0..3).map(|_| rng.next_u64()).collect();
+        assert_eq!(
+            first,
+            [
+                0x99ec_5f36_cb75_f2b4,
+                0xbf6e_1f78_4956_452a,
+                0x1a5f_849d_4933_e6e0
+            ]
+        );
+    }
+
+    #[test]
+    fn streams_are_reproducible_and_distinct() {
+        let draw = |mut rng: SimRng| (0..8).map(|_| rng.next_u64()).collect::<Vec<_>>();
+
+        assert_eq!(draw(SimRng::stream(7, 1)), draw(SimRng::stream(7, 1)));
+        assert_ne!(draw(SimRng::stream(7, 1)), draw(SimRng::stream(7, 2)));
+        assert_ne!(draw(SimRng::stream(7, 1)), draw(SimRng::stream(8, 1)));
+    }
+
+    #[test]
+    fn agents_get_their_own_stream() {
+        let market = |seed| {
+            let mut market = Market::new(MarketInfo {
+                name: "rng".to_owned(),
+                commodity: (),
+            })
+            .with_seed(seed);
+            let ids: Vec<AgentId> = (0..2).map(|_| market.register_with_default_acc()).collect();
+            (market, ids)
+        };
+
+        let (a, ids) = market(3);
+        let (b, _) = market(3);
+        assert_eq!(a.agent_rng(ids[0]), b.agent_rng(ids[0]));
+        assert_ne!(a.agent_rng(ids[0]), a.agent_rng(ids[1]));
+        assert_ne!(a.agent_rng(ids[0]), market(4).0.agent_rng(ids[0]));
+    }
+
+    #[test]
+    fn draws_stay_in_bounds() {
+        let mut rng = SimRng::seed_from_u64(42);
+        for _ in 0..1000 {
+            assert!((-3..=3).contains(&rng.range(-3..=3)));
+            assert!(rng.below(10) < 10);
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+        }
+        assert_eq!(rng.range(5..=5), 5);
+        rng.range(i64::MIN..=i64::MAX);
+
+        let mut seen = [false; 4];
+        (0..100).for_each(|_| seen[*rng.choose(&[0, 1, 2, 3]).unwrap()] = true);
+        assert!(seen.iter().all(|&x| x));
+        assert_eq!(rng.choose::<u8>(&[]), None);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    agent::AgentId, amount::Amount, fees::bps_of, order_book::Transaction, orders::flat::Order,
+    quantity::Quantity,
+};
+
+/// Delayed settlement of trades. Fills settle `lag` steps after the step they happened
+/// in, as set by `Market::start_step`, until then the bidder's payment and the asker's
+/// commodity are locked. A lag of zero settles every fill immediately.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettlementPolicy {
+    pub lag: u64,
+    // the failing asker pays this share of the undelivered value to the bidder
+    pub penalty_bps: i64,
+    // buy the undelivered units in the market on behalf of the bidder
+    pub buy_in: bool,
+}
+
+impl SettlementPolicy {
+    pub fn is_valid(&self) -> bool {
+        self.penalty_bps >= 0
+    }
+
+    pub fn penalty(&self, transaction: &Transaction, missing: Quantity) -> Amount {
+        let undelivered = transaction
+            .ask_gain
+            .pro_rata(missing, transaction.size)
+            .unwrap_or(transaction.ask_gain);
+
+        bps_of(undelivered, self.penalty_bps)
+    }
+}
+
+// a fill waiting for delivery
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Obligation {
+    pub due: u64,
+    pub bidder: AgentId,
+    pub asker: AgentId,
+    pub transaction: Transaction,
+}
+
+impl Obligation {
+    // what the bidder pays, charges included
+    pub fn payment(&self) -> Amount {
+        self.transaction.payment()
+    }
+
+    // the part of the fill covering `units`, amounts and charges pro rata
+    pub fn partial(&self, units: Quantity) -> Transaction {
+        self.transaction.partial(units)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeliveryFailure {
+    pub obligation: Obligation,
+    pub delivered: Quantity,
+    // paid by the asker to the bidder
+    pub penalty: Amount,
+    // market bid placed on behalf of the bidder for the undelivered units
+    pub buy_in: Option<Order>,
+}
+
+impl DeliveryFailure {
+    pub fn missing(&self) -> Quantity {
+        self.obligation.transaction.size - self.delivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::Account,
+        lending::LendingPolicy,
+        market::Market,
+        test_util::{self, try_trade},
+    };
+
+    fn market(policy: SettlementPolicy) -> (Market<()>, AgentId, AgentId) {
+        let mut market = test_util::market("settlement").with_settlement(policy);
+
+        let buyer = market.register_with_acc(Account {
+            money: Amount { as_int: 100 },
+            ..Default::default()
+        });
+        let seller = market.register_with_acc(Account {
+            commodity: Quantity::new(10),
+            ..Default::default()
+        });
+
+        (market, buyer, seller)
+    }
+
+    #[test]
+    fn fills_settle_after_the_lag() {
+        let (mut market, buyer, seller) = market(SettlementPolicy {
+            lag: 2,
+            ..Default::default()
+        });
+
+        market.start_step(1);
+        try_trade(&mut market, &[(seller, "A:0.10:6"), (buyer, "B:0.10:6")]);
+        assert_eq!(market.pending_obligations().len(), 1);
+        assert_eq!(market.pending_obligations()[0].due, 3);
+
+        // locked amounts can't back new orders
+        let rejected = try_trade(&mut market, &[(seller, "A:0.10:5"), (buyer, "B:0.01:50")]);
+        assert!(rejected.iter().all(Option::is_some));
+
+        let account = market.account(buyer).unwrap();
+        assert_eq!(account.money, Amount { as_int: 100 });
+        assert_eq!(account.locked_money, Amount { as_int: 60 });
+
+        market.start_step(2);
+        assert!(market.settle_obligations().is_empty());
+        assert_eq!(market.account(seller).unwrap().commodity, Quantity::new(10));
+
+        market.start_step(3);
+        assert!(market.settle_obligations().is_empty());
+        assert!(market.pending_obligations().is_empty());
+
+        let buyer = market.account(buyer).unwrap();
+        assert_eq!(buyer.money, Amount { as_int: 40 });
+        assert_eq!(buyer.commodity, Quantity::new(6));
+        assert_eq!(buyer.locked_money, Amount::new());
+
+        let seller = market.account(seller).unwrap();
+        assert_eq!(seller.money, Amount { as_int: 60 });
+        assert_eq!(seller.commodity, Quantity::new(4));
+        assert_eq!(seller.locked_commodity, Quantity::ZERO);
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    fn failed_delivery_is_penalised_and_bought_in() {
+        let (market, buyer, seller) = market(SettlementPolicy {
+            lag: 1,
+            penalty_bps: 1000,
+            buy_in: true,
+        });
+        let mut market = market.with_lending(LendingPolicy::default());
+        market.fund_lending_pool(Quantity::new(3));
+        let before = market.total_money();
+
+        // the seller ships most of its commodity out and sells short on top of the rest
+        assert!(market.inject_commodity(seller, Quantity::new(-7)));
+        market.start_step(1);
+        try_trade(&mut market, &[(seller, "A:0.10:6"), (buyer, "B:0.10:6")]);
+
+        // the pool is taken back before the short sale settles
+        market.recall(Quantity::new(3));
+
+        market.start_step(2);
+        let failures = market.settle_obligations();
+        assert_eq!(failures.len(), 1);
+        let (id, failure) = failures[0];
+        assert_eq!(id, seller);
+        assert_eq!(failure.delivered, Quantity::new(3));
+        assert_eq!(failure.missing(), Quantity::new(3));
+        assert_eq!(failure.penalty, Amount { as_int: 3 });
+        assert_eq!(failure.buy_in.unwrap().size, Quantity::new(3));
+
+        let account = market.account(seller).unwrap();
+        assert_eq!(account.commodity, Quantity::ZERO);
+        assert_eq!(account.money, Amount { as_int: 30 - 3 });
+
+        let other = market.register_with_acc(Account {
+            commodity: Quantity::new(5),
+            ..Default::default()
+        });
+        try_trade(&mut market, &[(other, "A:0.12:3")]);
+
+        market.start_step(3);
+        assert!(market.settle_obligations().is_empty());
+
+        let account = market.account(buyer).unwrap();
+        assert_eq!(account.commodity, Quantity::new(6));
+        assert_eq!(
+            account.money,
+            Amount {
+                as_int: 100 - 30 + 3 - 36
+            }
+        );
+        assert_eq!(market.total_money(), before);
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    fn short_sales_borrow_at_settlement() {
+        let (market, buyer, seller) = market(SettlementPolicy {
+            lag: 1,
+            ..Default::default()
+        });
+        let mut market = market.with_lending(LendingPolicy::default());
+        market.fund_lending_pool(Quantity::new(5));
+
+        market.start_step(1);
+        let rejected = try_trade(&mut market, &[(seller, "A:0.08:12"), (buyer, "B:0.08:12")]);
+        assert_eq!(rejected, [None, None]);
+
+        market.start_step(2);
+        assert!(market.settle_obligations().is_empty());
+
+        let account = market.account(seller).unwrap();
+        assert_eq!(account.commodity, Quantity::ZERO);
+        assert_eq!(account.borrowed_commodity, Quantity::new(2));
+        assert!(market.audit().is_empty());
+    }
+
+    #[test]
+    fn settlement_borrows_only_what_the_pool_lends() {
+        let (market, buyer, seller) = market(SettlementPolicy {
+            lag: 1,
+            ..Default::default()
+        });
+        let mut market = market.with_lending(LendingPolicy::default());
+        market.fund_lending_pool(Quantity::new(5));
+
+        market.start_step(1);
+        try_trade(&mut market, &[(seller, "A:0.08:12"), (buyer, "B:0.08:12")]);
+
+        // the units stay with the market, but only one of them may still be lent
+        market.recall(Quantity::new(4));
+        assert_eq!(market.market_account().commodity, Quantity::new(5));
+
+        market.start_step(2);
+        let failures = market.settle_obligations();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].1.delivered, Quantity::new(11));
+        assert_eq!(
+            market.account(seller).unwrap().borrowed_commodity,
+            Quantity::new(1)
+        );
+        assert!(market.audit().is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt::Display;
+
+use crate::{
+    engine::MatchingEngine,
+    market::{History, Market},
+    order_book::OrderBook,
+};
+
+// bumped whenever the layout of the market state changes
+pub const SNAPSHOT_VERSION: u32 = 6;
+
+/// Checkpoint of a market and the history its agents see next. Restoring it continues
+/// exactly where the snapshot was taken: balances, resting orders, pending settlements
+/// and the order id and time counters all come back as they were.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot<CommodityType, Engine = OrderBook> {
+    pub version: u32,
+    pub market: Market<CommodityType, Engine>,
+    pub history: History,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    UnsupportedVersion(u32),
+    // accounts don't match the ledger
+    Unbalanced,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {version} is not supported, expected {SNAPSHOT_VERSION}"
+            ),
+            SnapshotError::Unbalanced => write!(f, "snapshot accounts don't match its ledger"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl<CommodityType, Engine: MatchingEngine> Snapshot<CommodityType, Engine> {
+    pub fn restore(self) -> Result<(Market<CommodityType, Engine>, History), SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+        if !self.market.audit().is_empty() {
+            return Err(SnapshotError::Unbalanced);
+        }
+
+        Ok((self.market, self.history))
+    }
+}
+
+impl<CommodityType: Clone, Engine: MatchingEngine + Clone> Market<CommodityType, Engine> {
+    pub fn snapshot(&self, history: &History) -> Snapshot<CommodityType, Engine> {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            market: self.clone(),
+            history: history.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{
+        account::Account,
+        agent::{Agent, AgentId},
+        amount::Amount,
+        fees::{Fee, FeeSchedule},
+        market::MarketInfo,
+        orders::flat::OrderData,
+        quantity::Quantity,
+        rng::SimRng,
+        settlement::SettlementPolicy,
+    };
+
+    // keeps quoting around the last price, so its orders depend on the history
+    struct Quoter {
+        side: &'static str,
+        offset: i64,
+    }
+
+    impl Agent for Quoter {
+        type CommodityType = ();
+
+        fn setup(&mut self, _id: AgentId, _info: &MarketInfo<()>, _rng: SimRng) {}
+
+        fn produce_orders(
+            &mut self,
+            _account: &Account,
+            _info: &MarketInfo<()>,
+            history: &History,
+        ) -> Vec<OrderData> {
+            let price = history.market_price().unwrap_or(Amount { as_int: 50 });
+            let price: Amount = Amount {
+                as_int: (price.as_int + self.offset + history.step as i64 % 3).max(1),
+            };
+
+            vec![
+                format!("{}:{price}:2", self.side).parse().unwrap(),
+                format!("{}:1", self.side).parse().unwrap(),
+            ]
+        }
+    }
+
+    type Agents = Vec<(AgentId, Market<()>::AgentRefType)>;
+
+    fn market() -> (Market<()>, Agents) {
+        let mut market = Market::new(MarketInfo {
+            name: "snapshot".to_owned(),
+            commodity: (),
+        })
+        .with_fees(FeeSchedule {
+            taker: Fee::new(Amount { as_int: 1 }, 50),
+            ..Default::default()
+        })
+        .with_settlement(SettlementPolicy {
+            lag: 2,
+            penalty_bps: 500,
+            buy_in: false,
+        });
+
+        let agents = [("A", -2), ("B", 3), ("A", 1), ("B", -1)]
+            .into_iter()
+            .map(|(side, offset)| {
+                let id = market.register_with_acc(Account {
+                    commodity: Quantity::new(40),
+                    money: Amount::units(40),
+                    ..Default::default()
+                });
+                let agent: Market<()>::AgentRefType =
+                    RefCell::new(Box::new(Quoter { side, offset }));
+                (id, agent)
+            })
+            .collect();
+
+        (market, agents)
+    }
+
+    fn step(
+        market: &mut Market<()>,
+        agents: &[(AgentId, Market<()>::AgentRefType)],
+        history: &History,
+    ) -> History {
+        let step = history.step + 1;
+        market.start_step(step);
+        let failed_deliveries = market.settle_obligations();
+
+        let rejected_orders = market.agents_submit_orders(agents, history);
+        let transactions = market.process_submitted_orders(history.market_price());
+        let unfulfilled_orders = market.all_orders();
+        market.clear_reserves_and_orders();
+
+        History {
+            step,
+            transactions,
+            rejected_orders,
+            unfulfilled_orders,
+            failed_deliveries,
+            ..Default::default()
+        }
+    }
+
+    fn accounts(
+        market: &Market<()>,
+        agents: &[(AgentId, Market<()>::AgentRefType)],
+    ) -> Vec<Account> {
+        agents
+            .iter()
+            .flat_map(|(id, _)| market.account(*id))
+            .chain([market.market_account()])
+            .collect()
+    }
+
+    #[test]
+    fn restore_continues_identically() {
+        let (mut market, agents) = market();
+        let mut history = History::default();
+        for _ in 0..5 {
+            history = step(&mut market, &agents, &history);
+        }
+
+        let snapshot = market.snapshot(&history);
+        let (mut fork, mut fork_history) = snapshot.clone().restore().unwrap();
+
+        for _ in 0..10 {
+            history = step(&mut market, &agents, &history);
+            fork_history = step(&mut fork, &agents, &fork_history);
+
+            assert!(!history.transactions.is_empty());
+            assert_eq!(fork_history.transactions, history.transactions);
+            assert_eq!(fork_history.rejected_orders, history.rejected_orders);
+            assert_eq!(fork_history.failed_deliveries, history.failed_deliveries);
+            assert_eq!(accounts(&fork, &agents), accounts(&market, &agents));
+        }
+        assert_eq!(fork.ledger().postings(), market.ledger().postings());
+
+        // the snapshot is untouched by either run
+        let (restored, restored_history) = snapshot.restore().unwrap();
+        assert_eq!(restored_history.step, 5);
+        assert_ne!(accounts(&restored, &agents), accounts(&market, &agents));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let (market, _) = market();
+
+        let mut snapshot = market.snapshot(&History::default());
+        snapshot.version += 1;
+        assert_eq!(
+            snapshot.restore().err(),
+            Some(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialised_snapshots_continue_identically() {
+        let (mut market, agents) = market();
+        let mut history = History::default();
+        for _ in 0..5 {
+            history = step(&mut market, &agents, &history);
+        }
+
+        let json = serde_json::to_string(&market.snapshot(&history)).unwrap();
+        let snapshot: Snapshot<()> = serde_json::from_str(&json).unwrap();
+        let (mut restored, mut restored_history) = snapshot.restore().unwrap();
+
+        for _ in 0..10 {
+            history = step(&mut market, &agents, &history);
+            restored_history = step(&mut restored, &agents, &restored_history);
+            assert_eq!(restored_history.transactions, history.transactions);
+        }
+        assert_eq!(
+            serde_json::to_value(&restored).unwrap(),
+            serde_json::to_value(&market).unwrap()
+        );
+
+        // edited balances no longer match the ledger
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["market"]["market_account"]["money"] = 1.into();
+        let snapshot: Snapshot<()> = serde_json::from_value(value).unwrap();
+        assert_eq!(snapshot.restore().err(), Some(SnapshotError::Unbalanced));
+    }
+}
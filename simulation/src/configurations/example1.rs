@@ -5,7 +5,9 @@ use market::{
     account::Account,
     agent::{Agent as GenericAgent, AgentId},
     amount::Amount,
+    bankruptcy::Spawner,
     market::{History, Market, MarketInfo},
+    quantity::Quantity,
 };
 
 pub enum CommodityType {
@@ -20,8 +22,11 @@ type AgentRefType = RefCell<Box<dyn Agent>>;
 pub struct MarketConfiguration {
     pub market: Market<CommodityType>,
     pub agents: Vec<(AgentId, AgentRefType)>,
+    pub spawner: Option<Spawner<CommodityType>>,
 
     pub history: History,
+    // earlier steps the agents see along with the last one
+    pub history_window: usize,
     pub market_price: Option<Amount>,
     pub step: u64,
 }
@@ -35,7 +40,7 @@ impl MarketConfiguration {
 
         let simpl_buy_agents: Vec<Box<dyn Agent>> = std::iter::repeat(())
             .map(|_| BuyAgent::<CommodityType> {
-                bid_size: 1,
+                bid_size: Quantity::new(1),
                 bid_amount: 1,
                 period: 1,
                 innate_price: Some(Amount { as_int: 50 }),
@@ -51,7 +56,7 @@ impl MarketConfiguration {
 
         let simpl_sell_agents: Vec<Box<dyn Agent>> = std::iter::repeat(())
             .map(|_| SellAgent::<CommodityType> {
-                ask_size: 1,
+                ask_size: Quantity::new(1),
                 ask_amount: 2,
                 period: 2,
                 innate_price: Some(Amount { as_int: 25 }),
@@ -67,7 +72,7 @@ impl MarketConfiguration {
 
         let inc_sell_agents: Vec<Box<dyn Agent>> = std::iter::repeat(())
             .map(|_| IncSellAgent::<CommodityType> {
-                ask_size: 1,
+                ask_size: Quantity::new(1),
                 ask_amount: 2,
                 period: 1,
                 my_id: AgentId::new(0),
@@ -85,7 +90,7 @@ impl MarketConfiguration {
 
         let inc_buy_agents: Vec<Box<dyn Agent>> = std::iter::repeat(())
             .map(|_| IncBuyAgent::<CommodityType> {
-                bid_size: 1,
+                bid_size: Quantity::new(1),
                 bid_amount: 2,
                 period: 1,
                 my_id: AgentId::new(0),
@@ -111,11 +116,11 @@ impl MarketConfiguration {
         .flat_map(|x| x.into_iter())
         .map(|mut agent| {
             let id = market.register_with_acc(Account {
-                commodity: 100,
+                commodity: Quantity::new(100),
                 money: Amount { as_int: 1000 },
                 ..Default::default()
             });
-            agent.setup(id, &market.info);
+            agent.setup(id, &market.info, market.agent_rng(id));
             (id, RefCell::new(agent))
         })
         .collect();
@@ -123,36 +128,87 @@ impl MarketConfiguration {
         MarketConfiguration {
             market,
             agents,
+            spawner: None,
             history: Default::default(),
+            history_window: 10,
             market_price: Default::default(),
             step: 1,
         }
     }
 
     pub fn step(&mut self) -> Option<Amount> {
+        self.market.start_step(self.step);
+        let failed_deliveries = self.market.settle_obligations();
+
+        let margin_calls = self
+            .market_price
+            .map(|price| self.market.check_margins(price))
+            .unwrap_or_default();
+
         let rejected_orders = self
             .market
             .agents_submit_orders(self.agents.as_slice(), &self.history);
         let transactions = self.market.process_submitted_orders(self.market_price);
         let unfulfilled_orders = self.market.all_orders();
 
-        self.history = History {
+        let history = History {
             step: self.step,
             transactions,
             rejected_orders,
             unfulfilled_orders,
+            margin_calls,
+            short_interest: self.market.short_interest(),
+            portfolios: Default::default(),
+            failed_deliveries,
+            book: self.market.book_snapshot(),
+            fundamental: self.market.fundamental(),
+            window: Default::default(),
         };
+        self.history = std::mem::take(&mut self.history).advance(history, self.history_window);
 
         if !self.history.no_transactions() {
             self.market_price = self.history.market_price()
         }
+        if let Some(price) = self.market_price {
+            self.market.mark_portfolios(price);
+            self.history.portfolios = self.market.all_portfolio_stats(price);
+        }
 
         self.market.clear_reserves_and_orders();
+        self.market.accrue_interest();
+        if let Some(price) = self.market_price {
+            self.market.settle_lending(price);
+            self.replace_bankrupt(price);
+        }
         self.step += 1;
         self.market_price
     }
 }
 
+impl MarketConfiguration {
+    fn replace_bankrupt(&mut self, price: Amount) {
+        let retired = self.market.check_bankruptcies(price);
+        if retired.is_empty() {
+            return;
+        }
+
+        let market = &self.market;
+        self.agents.retain(|(id, _)| !market.is_retired(id));
+
+        let Some(spawner) = self.spawner.as_mut() else {
+            return;
+        };
+
+        for (id, account) in retired {
+            if let Some((mut agent, account)) = spawner(id, &account) {
+                let id = self.market.register_with_acc(account);
+                agent.setup(id, &self.market.info, self.market.agent_rng(id));
+                self.agents.push((id, RefCell::new(agent)));
+            }
+        }
+    }
+}
+
 impl Default for MarketConfiguration {
     fn default() -> Self {
         Self::new()
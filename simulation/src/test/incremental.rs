@@ -8,7 +8,7 @@ fn run() {
 
         println!(
             "market price: {}",
-            market_price.map_or("?".to_owned(), |x| x.as_int.to_string())
+            market_price.map_or("?".to_owned(), |x| x.to_string())
         );
     }
 }
@@ -5,6 +5,7 @@ use market::{
     agent::Agent as GenericAgent,
     amount::Amount,
     market::{History, Market, MarketInfo},
+    quantity::Quantity,
 };
 
 enum CommodityType {
@@ -22,7 +23,7 @@ fn run() {
     });
 
     let buy_agent: Box<dyn Agent> = Box::new(BuyAgent::<_> {
-        bid_size: 1,
+        bid_size: Quantity::new(1),
         bid_amount: 2,
         period: 2,
         innate_price: Some(Amount { as_int: 10 }),
@@ -30,7 +31,7 @@ fn run() {
     });
 
     let sell_agent: Box<dyn Agent> = Box::new(SellAgent::<_> {
-        ask_size: 1,
+        ask_size: Quantity::new(1),
         ask_amount: 2,
         period: 2,
         innate_price: Some(Amount { as_int: 8 }),
@@ -39,23 +40,24 @@ fn run() {
 
     let agents = [buy_agent, sell_agent].map(|mut agent| {
         let id = market.register_with_default_acc();
-        agent.setup(id, &market.info);
+        agent.setup(id, &market.info, market.agent_rng(id));
         (id, RefCell::new(agent))
     });
 
-    market.accounts.get_mut(&agents[0].0).unwrap().money += Amount { as_int: 30 };
-    market.accounts.get_mut(&agents[1].0).unwrap().commodity += 20;
+    market.inject_money(agents[0].0, Amount { as_int: 30 });
+    market.inject_commodity(agents[1].0, Quantity::new(20));
 
     let mut history = History::default();
     let mut market_price = None;
 
     for step in 1..=10 {
-        let bidder_money = market.accounts.get(&agents[0].0).unwrap().money.as_int;
-        let asker_comm = market.accounts.get(&agents[1].0).unwrap().commodity;
+        let bidder_money = market.account(agents[0].0).unwrap().money;
+        let asker_comm = market.account(agents[1].0).unwrap().commodity;
 
         println!("history: {}", history);
         println!("-------");
 
+        market.start_step(step);
         let rejected_orders = market.agents_submit_orders(agents.as_slice(), &history);
         let transactions = market.process_submitted_orders(market_price);
         let unfulfilled_orders = market.all_orders();
@@ -65,6 +67,7 @@ fn run() {
             transactions,
             rejected_orders,
             unfulfilled_orders,
+            ..Default::default()
         };
 
         if !history.no_transactions() {
@@ -73,21 +76,22 @@ fn run() {
 
         println!(
             "market price: {}",
-            market_price.map_or("?".to_owned(), |x| x.as_int.to_string())
+            market_price.map_or("?".to_owned(), |x| x.to_string())
         );
 
         market.clear_reserves_and_orders();
 
         println!(
-            "> buyer money: {bidder_money}->{:?}",
-            market.accounts.get(&agents[0].0).unwrap().money.as_int
+            "> buyer money: {bidder_money}->{}",
+            market.account(agents[0].0).unwrap().money
         );
         println!(
             "> seller comm: {asker_comm}->{:?}",
-            market.accounts.get(&agents[1].0).unwrap().commodity
+            market.account(agents[1].0).unwrap().commodity
         );
 
-        market.accounts.get_mut(&agents[0].0).unwrap().money += Amount { as_int: 5 };
-        market.accounts.get_mut(&agents[1].0).unwrap().commodity += 2;
+        market.inject_money(agents[0].0, Amount { as_int: 5 });
+        market.inject_commodity(agents[1].0, Quantity::new(2));
+        assert!(market.audit().is_empty());
     }
 }